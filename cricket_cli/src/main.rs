@@ -1,15 +1,16 @@
-use cricket::lexer;
+use cricket::lexer::{self, LexError};
 use cricket::midigen::MidiGen;
 use cricket::parser::Parser;
 use cricket::semantic::Semantic;
 use cricket::soundgen::render_midi_to_wav;
 use env_logger::Builder;
 use log::LevelFilter;
-use log::{debug, info};
+use log::debug;
 
 use clap::Parser as clap_Parser;
 
 use clap::ValueEnum;
+use std::path::PathBuf;
 use std::process;
 
 #[derive(clap_Parser, Debug)]
@@ -22,9 +23,23 @@ struct Cli {
     #[arg(short = 'g', long = "generate", value_enum, default_value_t = OutputType::Sound)]
     generate: OutputType,
 
+    /// Directory to write the generated files to, created if missing.
+    /// Defaults to the working directory
+    #[arg(short = 'o', long = "out-dir")]
+    out_dir: Option<PathBuf>,
+
     #[arg(long = "sf-path")]
     sf_path: Option<String>,
 
+    /// How to treat characters that are not part of the language: 'lenient'
+    /// skips them with a warning, 'strict' fails
+    #[arg(long = "lex-mode", value_enum, default_value_t = LexMode::Lenient)]
+    lex_mode: LexMode,
+
+    /// Treat semantic warnings, such as unused definitions, as errors
+    #[arg(long = "deny-warnings", action)]
+    deny_warnings: bool,
+
     #[arg(short = 'v', long = "verbose", action)]
     verbose: bool,
 }
@@ -35,6 +50,12 @@ enum OutputType {
     Sound,
 }
 
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum LexMode {
+    Strict,
+    Lenient,
+}
+
 fn init_logging(verbose: bool) {
     let mut builder = Builder::new();
 
@@ -66,26 +87,88 @@ fn main() {
     debug!("CLI arguments: {:?}", cli);
 
     debug!("Reading source file: {}", cli.file_path);
-    let tokens = lexer::tokenize(&content);
-    let mut parser = Parser::new(tokens);
-    let ast = parser.parse();
+    let lexed = lexer::lex(&content);
+    // `--lex-mode` only decides about stray characters, which the lexer
+    // skips. Anything else, such as an unclosed comment, loses source text.
+    let mut fatal = 0;
+    for error in lexed.errors.iter() {
+        let skipped = matches!(error, LexError::UnexpectedCharacter { .. });
+        let severity = if skipped && matches!(cli.lex_mode, LexMode::Lenient) {
+            "warning"
+        } else {
+            fatal += 1;
+            "error"
+        };
+        eprintln!("{}:{}: {}: {}", src, error.span(), severity, error);
+    }
+    if fatal > 0 {
+        eprintln!("Could not tokenize '{}' due to {} error(s)", src, fatal);
+        process::exit(1);
+    }
+
+    let mut parser = Parser::new(lexed.tokens);
+    let ast = parser.parse().unwrap_or_else(|errors| {
+        for error in errors.iter() {
+            eprintln!("{}:{}: error: {}", src, error.span(), error);
+        }
+        eprintln!("Could not parse '{}' due to {} error(s)", src, errors.len());
+        process::exit(1);
+    });
 
     debug!("{:#?}", ast);
 
     let mut semantic_analysis = Semantic::new(ast.clone());
-    let _ = semantic_analysis.analyze();
+    let analysis = semantic_analysis.analyze();
+    let warnings = semantic_analysis.warnings();
+    let severity = if cli.deny_warnings {
+        "error"
+    } else {
+        "warning"
+    };
+    for warning in warnings.iter() {
+        eprintln!("{}:{}: {}: {}", src, warning.span(), severity, warning);
+    }
+    if let Err(errors) = analysis {
+        for error in errors.iter() {
+            eprintln!("{}:{}: error: {}", src, error.span(), error);
+        }
+        eprintln!(
+            "Could not compile '{}' due to {} error(s)",
+            src,
+            errors.len()
+        );
+        process::exit(1);
+    }
+    if cli.deny_warnings && !warnings.is_empty() {
+        eprintln!(
+            "Could not compile '{}' due to {} warning(s) denied by --deny-warnings",
+            src,
+            warnings.len()
+        );
+        process::exit(1);
+    }
 
     debug!("Worked");
 
-    let created_words = match cli.generate {
-        OutputType::Midi => {
-            let mut midigen = MidiGen::new(&ast);
-            let results = midigen.generate();
-            results
+    let midigen = MidiGen::new(&ast);
+    let results = match &cli.out_dir {
+        Some(out_dir) => {
+            let written = std::fs::create_dir_all(out_dir)
+                .map_err(|e| e.to_string())
+                .and_then(|()| midigen.generate_in(out_dir).map_err(|e| e.to_string()));
+            written.unwrap_or_else(|e| {
+                eprintln!("Could not write to '{}': {}", out_dir.display(), e);
+                process::exit(1);
+            })
         }
+        None => midigen.generate().unwrap_or_else(|e| {
+            eprintln!("Could not generate '{}': {}", src, e);
+            process::exit(1);
+        }),
+    };
+    let created_words = match cli.generate {
+        OutputType::Midi => results,
         OutputType::Sound => {
-            let mut midigen = MidiGen::new(&ast);
-            let results = midigen.generate();
             let sf2 = cli.sf_path.as_deref().unwrap_or_else(||  {
                 eprintln!("No SoundFont Path has been passed while trying to generate a Sound. Please use the --sf-path argument to pass a path to the soundfont.");
                 process::exit(1);
@@ -1,38 +1,470 @@
+use std::borrow::Cow;
+use std::fmt;
+
+pub use crate::span::Span;
+use crate::timing::Beats;
+
+/// A meter such as 3/4: `numerator` beats of a `1/denominator` note per bar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimeSignature {
+    pub numerator: u8,
+    pub denominator: u8,
+}
+
+impl TimeSignature {
+    /// 4/4, used when neither the song nor the section sets a meter.
+    pub const COMMON: TimeSignature = TimeSignature {
+        numerator: 4,
+        denominator: 4,
+    };
+
+    /// Whether the meter has at least one beat per bar, on a note value that
+    /// is a power of two.
+    pub fn is_valid(&self) -> bool {
+        self.numerator > 0 && self.denominator.is_power_of_two()
+    }
+
+    /// Length of one bar, if the meter is valid.
+    pub fn bar_length(&self) -> Option<Beats> {
+        self.is_valid()
+            .then(|| Beats::new(u64::from(self.numerator) * 4, u64::from(self.denominator)))
+    }
+
+    /// Whether `length` makes up a whole number of bars.
+    pub fn is_whole_bars(&self, length: Beats) -> bool {
+        let Some(bar) = self.bar_length() else {
+            return false;
+        };
+        // `length / bar` is a whole number when its numerator divides evenly.
+        let num = u128::from(length.numerator()) * u128::from(bar.denominator());
+        let den = u128::from(length.denominator()) * u128::from(bar.numerator());
+        den != 0 && num.is_multiple_of(den)
+    }
+}
+
+impl fmt::Display for TimeSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+/// A dynamic marking, from pianissimo to fortissimo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Dynamic {
+    Pp,
+    P,
+    Mp,
+    Mf,
+    F,
+    Ff,
+}
+
+impl Dynamic {
+    pub fn from_name(name: &str) -> Option<Dynamic> {
+        Some(match name {
+            "pp" => Dynamic::Pp,
+            "p" => Dynamic::P,
+            "mp" => Dynamic::Mp,
+            "mf" => Dynamic::Mf,
+            "f" => Dynamic::F,
+            "ff" => Dynamic::Ff,
+            _ => return None,
+        })
+    }
+
+    /// MIDI velocity the marking is played at.
+    pub fn velocity(&self) -> u8 {
+        match self {
+            Dynamic::Pp => 33,
+            Dynamic::P => 49,
+            Dynamic::Mp => 64,
+            Dynamic::Mf => 80,
+            Dynamic::F => 96,
+            Dynamic::Ff => 112,
+        }
+    }
+}
+
+impl fmt::Display for Dynamic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Dynamic::Pp => "pp",
+            Dynamic::P => "p",
+            Dynamic::Mp => "mp",
+            Dynamic::Mf => "mf",
+            Dynamic::F => "f",
+            Dynamic::Ff => "ff",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Whether a hairpin gets louder or softer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HairpinKind {
+    Crescendo,
+    Diminuendo,
+}
+
+impl fmt::Display for HairpinKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HairpinKind::Crescendo => write!(f, "crescendo"),
+            HairpinKind::Diminuendo => write!(f, "diminuendo"),
+        }
+    }
+}
+
+/// How far apart the notes of a chord are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Spread {
+    /// All notes within an octave of the lowest one.
+    #[default]
+    Close,
+    /// Every second note from the bottom raised by an octave.
+    Open,
+    /// The second highest note dropped by an octave.
+    Drop2,
+}
+
+/// Voicing options of a chord, as in `Note(Am, octave=3, inversion=1)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Voicing {
+    /// Octave of the root, 4 being the octave that starts at middle C.
+    pub octave: Option<u8>,
+    pub inversion: Option<u8>,
+    pub spread: Spread,
+}
+
+/// A number written out, or taken from the pattern parameter it names, as in
+/// `vel=vel`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value<T> {
+    Literal(T),
+    Parameter(String),
+}
+
+impl<T: Copy> Value<T> {
+    /// The number, unless it still names a parameter.
+    pub fn literal(&self) -> Option<T> {
+        match self {
+            Value::Literal(value) => Some(*value),
+            Value::Parameter(_) => None,
+        }
+    }
+}
+
+/// A value passed to a parameter, such as `Am`, `C#4` or `90`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Argument {
+    Symbol(String),
+    Number(u32),
+}
+
+impl fmt::Display for Argument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Argument::Symbol(symbol) => write!(f, "{}", symbol),
+            Argument::Number(number) => write!(f, "{}", number),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Instrument {
     pub name: String,
     pub type_: String,
     pub midi_path: String,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub enum PatternEvent {
-    Note { chord: String, duration: (u8, u8) },
-    Wait { duration: (u8, u8) },
+    /// A chord, or the pattern parameter that gives it.
+    Note {
+        chord: String,
+        voicing: Voicing,
+        /// MIDI velocity set with `vel=`, overriding the dynamics.
+        velocity: Option<Value<u8>>,
+        duration: (u8, u8),
+        span: Span,
+    },
+    /// A single note such as `C#4`, with octave 4 starting at middle C, or
+    /// the pattern parameter that gives it.
+    Pitch {
+        pitch: String,
+        velocity: Option<Value<u8>>,
+        duration: (u8, u8),
+        span: Span,
+    },
+    /// A rest, written `Wait()` or `Rest()`. A rest of whole `bars`, as in
+    /// `[17] Wait(4 bars)`, only gives its first step, which is also its last
+    /// in `duration`, and lasts as long as that many bars of the meter it is
+    /// played in.
+    Wait {
+        bars: Option<u16>,
+        duration: (u8, u8),
+        span: Span,
+    },
+    /// Moves the velocity of the notes starting within `duration` gradually
+    /// from `from`, or the pattern's dynamics when not given, to `to`, as in
+    /// `Crescendo(p, f)` or `Diminuendo(pp)`.
+    Hairpin {
+        kind: HairpinKind,
+        from: Option<Dynamic>,
+        to: Dynamic,
+        duration: (u8, u8),
+        span: Span,
+    },
+    /// Changes the tempo, in beats per minute, at the start of `duration`.
+    Tempo {
+        bpm: u16,
+        duration: (u8, u8),
+        span: Span,
+    },
+}
+
+impl PatternEvent {
+    pub fn span(&self) -> Span {
+        match self {
+            PatternEvent::Note { span, .. }
+            | PatternEvent::Pitch { span, .. }
+            | PatternEvent::Hairpin { span, .. }
+            | PatternEvent::Wait { span, .. }
+            | PatternEvent::Tempo { span, .. } => *span,
+        }
+    }
+
+    /// First and last step of the event, both 1-based and inclusive.
+    pub fn duration(&self) -> (u8, u8) {
+        match self {
+            PatternEvent::Note { duration, .. }
+            | PatternEvent::Pitch { duration, .. }
+            | PatternEvent::Hairpin { duration, .. }
+            | PatternEvent::Wait { duration, .. }
+            | PatternEvent::Tempo { duration, .. } => *duration,
+        }
+    }
+}
+
+/// How the chords of a channel follow each other, set with
+/// `voice_leading: smooth` or `voice_leading: none`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VoiceLeading {
+    /// Every chord voiced as written.
+    None,
+    /// Every chord moved to the inversion and octave closest to the chord
+    /// before it, with all notes between the `range` pitches when given, as
+    /// in `smooth(C3, C5)`. A chord with no voicing in the range, which
+    /// semantic analysis reports, is voiced as written.
+    Smooth { range: Option<(String, String)> },
 }
 
 #[derive(Debug, Clone)]
 pub struct Pattern {
     pub name: String,
+    /// Declared parameter names, each with its span.
+    pub params: Vec<(String, Span)>,
+    /// Overrides the voice leading of the channels playing the pattern.
+    pub voice_leading: Option<(VoiceLeading, Span)>,
+    /// Overrides the dynamics of the sections playing the pattern.
+    pub dynamics: Option<(Dynamic, Span)>,
     pub events: Vec<PatternEvent>,
+    pub span: Span,
+}
+
+impl Pattern {
+    /// The pattern with every parameter replaced by the argument `args` pass
+    /// for it, borrowed as is when it has no parameters.
+    pub fn bind(&self, args: &[(Argument, Span)]) -> Cow<'_, Pattern> {
+        if self.params.is_empty() {
+            return Cow::Borrowed(self);
+        }
+        let argument = |name: &str| {
+            let index = self.params.iter().position(|(param, _)| param == name)?;
+            args.get(index).map(|(arg, _)| arg)
+        };
+        let symbol = |text: &mut String| {
+            if let Some(arg) = argument(text) {
+                *text = arg.to_string();
+            }
+        };
+        let velocity = |velocity: &mut Option<Value<u8>>| {
+            if let Some(Value::Parameter(name)) = velocity {
+                *velocity = match argument(name) {
+                    Some(Argument::Number(number)) => {
+                        Some(Value::Literal((*number).min(127) as u8))
+                    }
+                    _ => None,
+                };
+            }
+        };
+        let mut bound = self.clone();
+        for event in &mut bound.events {
+            match event {
+                PatternEvent::Note {
+                    chord, velocity: v, ..
+                } => {
+                    symbol(chord);
+                    velocity(v);
+                }
+                PatternEvent::Pitch {
+                    pitch, velocity: v, ..
+                } => {
+                    symbol(pitch);
+                    velocity(v);
+                }
+                _ => {}
+            }
+        }
+        Cow::Owned(bound)
+    }
+
+    /// How long the pattern lasts when played in `meter`, up to the end of
+    /// its last event. `None` if it rests for whole bars of an invalid meter.
+    pub fn length_in(&self, meter: TimeSignature) -> Option<Beats> {
+        self.events.iter().try_fold(Beats::ZERO, |length, event| {
+            let end = match event {
+                PatternEvent::Wait {
+                    bars: Some(bars),
+                    duration: (first, _),
+                    ..
+                } => Beats::step_start(*first) + meter.bar_length()?.scale(u64::from(*bars), 1),
+                _ => Beats::step_end(event.duration().1),
+            };
+            Some(length.max(end))
+        })
+    }
+}
+
+/// A change applied to whatever a call plays, written after the call. A
+/// call's transforms apply one after another, in the order they are written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transform {
+    /// Moves every note up or down by this many semitones, written
+    /// `.transpose(5)` or `^ +5`.
+    Transpose(i32),
+    /// Plays the notes and rests back to front, written `.reverse()` or
+    /// `.retrograde()`. Tempo changes and hairpins stay where they are.
+    Reverse,
+    /// Makes every step this many times longer, written `.augment(2)`.
+    Augment(u8),
+    /// Makes every step this many times shorter, written `.diminish(2)`.
+    Diminish(u8),
+    /// Mirrors every pitch around the `around` pitch, written
+    /// `.invert(around=C4)`.
+    Invert { around: String },
+    /// Moves the notes and rests of the first this many steps to the end,
+    /// or of the last ones to the front when negative, written `.rotate(4)`.
+    /// Tempo changes and hairpins stay where they are.
+    Rotate(i32),
+}
+
+impl Transform {
+    /// The name the transform is written with.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transform::Transpose(_) => "transpose",
+            Transform::Reverse => "reverse",
+            Transform::Augment(_) => "augment",
+            Transform::Diminish(_) => "diminish",
+            Transform::Invert { .. } => "invert",
+            Transform::Rotate(_) => "rotate",
+        }
+    }
+}
+
+/// A call such as `groove(Am, 90)` in a channel.
+#[derive(Debug, Clone)]
+pub struct PatternCall {
+    pub name: String,
+    /// Arguments, each with its span.
+    pub args: Vec<(Argument, Span)>,
+    /// Transforms in the order they are written, each with its span.
+    pub transforms: Vec<(Transform, Span)>,
+    pub span: Span,
+}
+
+/// One entry of a `+` separated list of calls.
+#[derive(Debug, Clone)]
+pub enum Sequence<T> {
+    Call(T),
+    /// `body` played `count` times in a row, written `intro() * 4` or
+    /// `repeat 4 { intro() + fill() }`.
+    Repeat {
+        count: u32,
+        body: Vec<Sequence<T>>,
+        span: Span,
+    },
+}
+
+impl<T> Sequence<T> {
+    /// Every call as written, each once.
+    pub fn calls(&self) -> Vec<&T> {
+        match self {
+            Sequence::Call(call) => vec![call],
+            Sequence::Repeat { body, .. } => body.iter().flat_map(Sequence::calls).collect(),
+        }
+    }
+
+    /// The calls in the order they are played, with repeats unrolled as
+    /// they are reached rather than all at once.
+    pub fn played(&self) -> Box<dyn Iterator<Item = &T> + '_> {
+        match self {
+            Sequence::Call(call) => Box::new(std::iter::once(call)),
+            Sequence::Repeat { count, body, .. } => {
+                Box::new((0..*count).flat_map(move |_| body.iter().flat_map(Sequence::played)))
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Channel {
     pub name: String,
-    pub pattern_calls: Vec<String>,
+    /// Instrument named after `uses`, with the span of its name.
+    pub instrument: Option<(String, Span)>,
+    pub voice_leading: Option<(VoiceLeading, Span)>,
+    pub pattern_calls: Vec<Sequence<PatternCall>>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct Section {
     pub name: String,
+    /// Declared parameter names, each with its span. Channels forward them
+    /// to their pattern calls, as in `groove(key, 90)`.
+    pub params: Vec<(String, Span)>,
+    /// Tempo override for this section, in beats per minute.
+    pub tempo: Option<(u16, Span)>,
+    /// Meter override for this section.
+    pub time_signature: Option<(TimeSignature, Span)>,
+    /// Loudness of the notes of this section.
+    pub dynamics: Option<(Dynamic, Span)>,
     pub channels: Vec<Channel>,
+    pub span: Span,
+}
+
+/// A call such as `Verse(Am)` in a song.
+#[derive(Debug, Clone)]
+pub struct SectionCall {
+    pub name: String,
+    /// Arguments, each with its span.
+    pub args: Vec<(Argument, Span)>,
+    /// Transforms in the order they are written, each with its span.
+    pub transforms: Vec<(Transform, Span)>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct Song {
     pub name: String,
-    pub entry_sections: Vec<String>,
+    /// Tempo of the song in beats per minute, 120 when not given.
+    pub tempo: Option<(u16, Span)>,
+    /// Meter of the song, 4/4 when not given.
+    pub time_signature: Option<(TimeSignature, Span)>,
+    pub entry_sections: Vec<Sequence<SectionCall>>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -42,3 +474,14 @@ pub enum TopLevel {
     Section(Section),
     Song(Song),
 }
+
+impl TopLevel {
+    pub fn span(&self) -> Span {
+        match self {
+            TopLevel::Instrument(instrument) => instrument.span,
+            TopLevel::Pattern(pattern) => pattern.span,
+            TopLevel::Section(section) => section.span,
+            TopLevel::Song(song) => song.span,
+        }
+    }
+}
@@ -0,0 +1,711 @@
+use thiserror::Error;
+
+use crate::ast::{Spread, Voicing};
+
+/// MIDI number of middle C. Chord roots are placed in the octave above it.
+const MIDDLE_C: i16 = 60;
+
+/// Chord qualities and the intervals of their notes above the root, in
+/// semitones.
+const QUALITIES: [(&str, &[u8]); 16] = [
+    ("", &[0, 4, 7]),
+    ("m", &[0, 3, 7]),
+    ("7", &[0, 4, 7, 10]),
+    ("maj7", &[0, 4, 7, 11]),
+    ("m7", &[0, 3, 7, 10]),
+    ("dim", &[0, 3, 6]),
+    ("dim7", &[0, 3, 6, 9]),
+    ("aug", &[0, 4, 8]),
+    ("sus2", &[0, 2, 7]),
+    ("sus4", &[0, 5, 7]),
+    ("add9", &[0, 4, 7, 14]),
+    ("6", &[0, 4, 7, 9]),
+    ("9", &[0, 4, 7, 10, 14]),
+    ("11", &[0, 4, 7, 10, 14, 17]),
+    ("13", &[0, 4, 7, 10, 14, 17, 21]),
+    ("m7b5", &[0, 3, 6, 10]),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ChordError {
+    #[error("{symbol:?} does not start with a note name from A to G")]
+    InvalidRoot { symbol: String },
+    #[error("{quality:?} is not a known chord quality")]
+    UnknownQuality { quality: String },
+    #[error("bass note {bass:?} is not a note name from A to G")]
+    InvalidBass { bass: String },
+    #[error("inversion {inversion} needs at least {} chord notes, but there are {notes}", inversion + 1)]
+    InversionTooHigh { inversion: u8, notes: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum VoicingError {
+    #[error("the inversion is set both in the chord symbol and as an option")]
+    ConflictingInversion,
+    #[error("inversion {inversion} needs at least {} chord notes, but there are {notes}", inversion + 1)]
+    InversionTooHigh { inversion: u8, notes: usize },
+    #[error("the chord reaches MIDI note {number}, outside of the MIDI range 0 to 127")]
+    OutOfRange { number: i32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PitchError {
+    #[error("{pitch:?} does not start with a note name from A to G")]
+    InvalidName { pitch: String },
+    #[error("{pitch:?} needs an octave number after the note name, e.g. `C4`")]
+    InvalidOctave { pitch: String },
+    #[error("{pitch:?} is MIDI note {number}, outside of the MIDI range 0 to 127")]
+    OutOfRange { pitch: String, number: i32 },
+}
+
+/// A chord symbol such as `Bbm7`, `C/E` or `Am/2`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chord {
+    /// MIDI number of the root, between B3 and B#4 as parsed.
+    pub root: u8,
+    /// The quality as written, e.g. `maj7`, or empty for a major triad.
+    pub quality: &'static str,
+    /// Semitones of the chord tones above the root, starting with the root.
+    pub intervals: &'static [u8],
+    /// MIDI number of a slash bass note, the first one of its name below the
+    /// root.
+    pub bass: Option<u8>,
+    /// Inversion written after a slash, as in `Am/2`.
+    pub inversion: Option<u8>,
+}
+
+impl Chord {
+    /// MIDI numbers of the chord from the bottom up, bass note first, with
+    /// the inversion from the symbol applied.
+    pub fn notes(&self) -> Vec<u8> {
+        self.voice(&Voicing::default())
+            .expect("chords around middle C fit in the MIDI range")
+    }
+
+    /// The chord moved up or down by `semitones`, slash bass included.
+    pub fn transposed(&self, semitones: i32) -> Result<Chord, VoicingError> {
+        let shift = |note: u8| {
+            let number = i32::from(note).saturating_add(semitones);
+            u8::try_from(number)
+                .ok()
+                .filter(|number| *number <= 127)
+                .ok_or(VoicingError::OutOfRange { number })
+        };
+        Ok(Chord {
+            root: shift(self.root)?,
+            bass: self.bass.map(shift).transpose()?,
+            ..self.clone()
+        })
+    }
+
+    /// MIDI numbers of the chord from the bottom up, voiced as asked: the
+    /// root moved to `octave`, the lowest notes raised by an octave once per
+    /// inversion, then spread. A slash bass goes below all of that.
+    pub fn voice(&self, voicing: &Voicing) -> Result<Vec<u8>, VoicingError> {
+        let inversion = match (self.inversion, voicing.inversion) {
+            (Some(_), Some(_)) => return Err(VoicingError::ConflictingInversion),
+            (symbol, option) => symbol.or(option).unwrap_or(0),
+        };
+        if usize::from(inversion) >= self.intervals.len() {
+            return Err(VoicingError::InversionTooHigh {
+                inversion,
+                notes: self.intervals.len(),
+            });
+        }
+
+        let octave_shift = 12 * (i32::from(voicing.octave.unwrap_or(4)) - 4);
+        let root = i32::from(self.root) + octave_shift;
+        let mut notes: Vec<i32> = self
+            .intervals
+            .iter()
+            .map(|interval| root + i32::from(*interval))
+            .collect();
+        for _ in 0..inversion {
+            let lowest = notes.remove(0);
+            notes.push(lowest + 12);
+            notes.sort();
+        }
+        match voicing.spread {
+            Spread::Close => {}
+            Spread::Open => {
+                for note in notes.iter_mut().skip(1).step_by(2) {
+                    *note += 12;
+                }
+            }
+            Spread::Drop2 => {
+                let second_highest = notes.len() - 2;
+                notes[second_highest] -= 12;
+            }
+        }
+        notes.sort();
+        if let Some(bass) = self.bass {
+            let lowest = notes[0];
+            let below = (lowest - i32::from(bass)).rem_euclid(12);
+            notes.insert(0, lowest - if below == 0 { 12 } else { below });
+        }
+
+        notes
+            .into_iter()
+            .map(|number| {
+                u8::try_from(number)
+                    .ok()
+                    .filter(|number| *number <= 127)
+                    .ok_or(VoicingError::OutOfRange { number })
+            })
+            .collect()
+    }
+}
+
+/// How far the voices move from `from` to `to`: every note's distance in
+/// semitones to the nearest note of the other chord, summed both ways.
+fn movement(from: &[u8], to: &[u8]) -> u32 {
+    let nearest = |note: u8, chord: &[u8]| {
+        chord
+            .iter()
+            .map(|other| u32::from(note.abs_diff(*other)))
+            .min()
+            .unwrap_or(0)
+    };
+    to.iter().map(|note| nearest(*note, from)).sum::<u32>()
+        + from.iter().map(|note| nearest(*note, to)).sum::<u32>()
+}
+
+/// Voices `chord` in the inversion and octave that moves the least from
+/// `previous`, keeping every note within `range`, lowest and highest MIDI
+/// number included. Returns `None` when no voicing fits in the range.
+pub fn lead_voices(
+    chord: &Chord,
+    spread: Spread,
+    previous: &[u8],
+    range: (u8, u8),
+) -> Option<Vec<u8>> {
+    let (low, high) = range;
+    let mut best: Option<(u32, Vec<u8>)> = None;
+    for inversion in 0..chord.intervals.len() as u8 {
+        for octave in 0..=9 {
+            let voicing = Voicing {
+                octave: Some(octave),
+                inversion: Some(inversion),
+                spread,
+            };
+            let Ok(notes) = chord.voice(&voicing) else {
+                continue;
+            };
+            if notes.iter().any(|note| *note < low || *note > high) {
+                continue;
+            }
+            let cost = movement(previous, &notes);
+            if best.as_ref().is_none_or(|(best_cost, _)| cost < *best_cost) {
+                best = Some((cost, notes));
+            }
+        }
+    }
+    best.map(|(_, notes)| notes)
+}
+
+/// Splits a note name such as `C`, `F#` or `Bb` off the front of `text`.
+/// Returns its distance from C in semitones, from -1 for `Cb` to 12 for
+/// `B#`, and the rest of the text.
+pub fn split_note_name(text: &str) -> Option<(i16, &str)> {
+    let mut chars = text.chars();
+    let natural = match chars.next()? {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let rest = chars.as_str();
+    if let Some(rest) = rest.strip_prefix('#') {
+        Some((natural + 1, rest))
+    } else if let Some(rest) = rest.strip_prefix('b') {
+        Some((natural - 1, rest))
+    } else {
+        Some((natural, rest))
+    }
+}
+
+/// Note names of the pitch classes from C up, spelled with sharps.
+const PITCH_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// MIDI number of a pitch such as `C#4` or `Bb2`, where `C4` is middle C.
+/// The octave below octave 0 is written `-1`, as in `C-1`.
+pub fn parse_pitch(pitch: &str) -> Result<u8, PitchError> {
+    let (semitone, octave) = split_note_name(pitch).ok_or_else(|| PitchError::InvalidName {
+        pitch: pitch.to_string(),
+    })?;
+    let octave: i32 = octave
+        .parse()
+        .ok()
+        .filter(|_| {
+            let digits = octave.strip_prefix('-').unwrap_or(octave);
+            digits.chars().all(|c| c.is_ascii_digit())
+        })
+        .ok_or_else(|| PitchError::InvalidOctave {
+            pitch: pitch.to_string(),
+        })?;
+    let number = (octave + 1) * 12 + i32::from(semitone);
+    u8::try_from(number)
+        .ok()
+        .filter(|number| *number <= 127)
+        .ok_or_else(|| PitchError::OutOfRange {
+            pitch: pitch.to_string(),
+            number,
+        })
+}
+
+/// Name of the pitch with MIDI number `number`, spelled with a sharp where
+/// it needs an accidental, e.g. `C#4`.
+pub fn pitch_name(number: u8) -> String {
+    let octave = i32::from(number / 12) - 1;
+    format!("{}{}", PITCH_NAMES[usize::from(number % 12)], octave)
+}
+
+/// Parses a chord symbol: a root note name, an optional quality and
+/// optionally a `/` followed by a bass note name or an inversion number.
+pub fn parse(symbol: &str) -> Result<Chord, ChordError> {
+    let (root, rest) = split_note_name(symbol).ok_or_else(|| ChordError::InvalidRoot {
+        symbol: symbol.to_string(),
+    })?;
+    let (quality, bass) = match rest.split_once('/') {
+        Some((quality, bass)) => (quality, Some(bass)),
+        None => (rest, None),
+    };
+    let (quality, intervals) = QUALITIES
+        .iter()
+        .find(|(name, _)| *name == quality)
+        .copied()
+        .ok_or_else(|| ChordError::UnknownQuality {
+            quality: quality.to_string(),
+        })?;
+
+    let root = MIDDLE_C + root;
+    let mut inversion = None;
+    let bass = match bass {
+        Some(number) if !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) => {
+            let number = number.parse().unwrap_or(u8::MAX);
+            if usize::from(number) >= intervals.len() {
+                return Err(ChordError::InversionTooHigh {
+                    inversion: number,
+                    notes: intervals.len(),
+                });
+            }
+            inversion = Some(number);
+            None
+        }
+        Some(bass) => {
+            let pitch = match split_note_name(bass) {
+                Some((pitch, "")) => pitch,
+                _ => {
+                    return Err(ChordError::InvalidBass {
+                        bass: bass.to_string(),
+                    });
+                }
+            };
+            let below = (root - MIDDLE_C - pitch).rem_euclid(12);
+            Some((root - if below == 0 { 12 } else { below }) as u8)
+        }
+        None => None,
+    };
+
+    Ok(Chord {
+        root: root as u8,
+        quality,
+        intervals,
+        bass,
+        inversion,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notes(symbol: &str) -> Vec<u8> {
+        parse(symbol).unwrap().notes()
+    }
+
+    #[test]
+    fn every_root() {
+        let roots = [
+            ("Cb", 59),
+            ("C", 60),
+            ("C#", 61),
+            ("Db", 61),
+            ("D", 62),
+            ("D#", 63),
+            ("Eb", 63),
+            ("E", 64),
+            ("Fb", 64),
+            ("E#", 65),
+            ("F", 65),
+            ("F#", 66),
+            ("Gb", 66),
+            ("G", 67),
+            ("G#", 68),
+            ("Ab", 68),
+            ("A", 69),
+            ("A#", 70),
+            ("Bb", 70),
+            ("B", 71),
+            ("B#", 72),
+        ];
+        for (symbol, root) in roots {
+            let chord = parse(symbol).unwrap();
+            assert_eq!(chord.root, root, "{symbol}");
+            assert_eq!(chord.notes(), vec![root, root + 4, root + 7], "{symbol}");
+        }
+    }
+
+    #[test]
+    fn every_quality() {
+        let chords: [(&str, &[u8]); 16] = [
+            ("C", &[60, 64, 67]),
+            ("Cm", &[60, 63, 67]),
+            ("C7", &[60, 64, 67, 70]),
+            ("Cmaj7", &[60, 64, 67, 71]),
+            ("Cm7", &[60, 63, 67, 70]),
+            ("Cdim", &[60, 63, 66]),
+            ("Cdim7", &[60, 63, 66, 69]),
+            ("Caug", &[60, 64, 68]),
+            ("Csus2", &[60, 62, 67]),
+            ("Csus4", &[60, 65, 67]),
+            ("Cadd9", &[60, 64, 67, 74]),
+            ("C6", &[60, 64, 67, 69]),
+            ("C9", &[60, 64, 67, 70, 74]),
+            ("C11", &[60, 64, 67, 70, 74, 77]),
+            ("C13", &[60, 64, 67, 70, 74, 77, 81]),
+            ("Cm7b5", &[60, 63, 66, 70]),
+        ];
+        for (symbol, expected) in chords {
+            assert_eq!(notes(symbol), expected, "{symbol}");
+        }
+        let tested: Vec<_> = chords.iter().map(|(symbol, _)| &symbol[1..]).collect();
+        let known: Vec<_> = QUALITIES.iter().map(|(quality, _)| *quality).collect();
+        assert_eq!(tested, known);
+    }
+
+    #[test]
+    fn qualities_on_accidental_roots() {
+        assert_eq!(notes("Am"), vec![69, 72, 76]);
+        assert_eq!(notes("Bbm7"), vec![70, 73, 77, 80]);
+        assert_eq!(notes("F#dim7"), vec![66, 69, 72, 75]);
+        assert_eq!(notes("Ebmaj7"), vec![63, 67, 70, 74]);
+        assert_eq!(notes("Bm7b5"), vec![71, 74, 77, 81]);
+        assert_eq!(parse("Abm7b5").unwrap().quality, "m7b5");
+    }
+
+    #[test]
+    fn slash_bass_goes_below_the_root() {
+        assert_eq!(notes("C/E"), vec![52, 60, 64, 67]);
+        assert_eq!(notes("C/G"), vec![55, 60, 64, 67]);
+        assert_eq!(notes("C/B"), vec![59, 60, 64, 67]);
+        assert_eq!(notes("C/C"), vec![48, 60, 64, 67]);
+        assert_eq!(notes("Am7/G"), vec![67, 69, 72, 76, 79]);
+        assert_eq!(notes("F#m/C#"), vec![61, 66, 69, 73]);
+        assert_eq!(notes("Bb/D"), vec![62, 70, 74, 77]);
+        assert_eq!(parse("D/F#").unwrap().bass, Some(54));
+    }
+
+    #[test]
+    fn invalid_symbols() {
+        assert_eq!(
+            parse("H"),
+            Err(ChordError::InvalidRoot {
+                symbol: "H".to_string()
+            })
+        );
+        assert!(matches!(parse(""), Err(ChordError::InvalidRoot { .. })));
+        assert!(matches!(parse("7"), Err(ChordError::InvalidRoot { .. })));
+        assert!(matches!(parse("c"), Err(ChordError::InvalidRoot { .. })));
+        assert_eq!(
+            parse("Cmaj9"),
+            Err(ChordError::UnknownQuality {
+                quality: "maj9".to_string()
+            })
+        );
+        assert!(matches!(
+            parse("C##"),
+            Err(ChordError::UnknownQuality { .. })
+        ));
+        assert!(matches!(
+            parse("Cm7b5b"),
+            Err(ChordError::UnknownQuality { .. })
+        ));
+        assert_eq!(
+            parse("C/H"),
+            Err(ChordError::InvalidBass {
+                bass: "H".to_string()
+            })
+        );
+        assert!(matches!(parse("C/"), Err(ChordError::InvalidBass { .. })));
+        assert!(matches!(parse("C/Em"), Err(ChordError::InvalidBass { .. })));
+        assert!(matches!(
+            parse("C/E/G"),
+            Err(ChordError::InvalidBass { .. })
+        ));
+    }
+
+    #[test]
+    fn error_messages() {
+        assert_eq!(
+            parse("H7").unwrap_err().to_string(),
+            "\"H7\" does not start with a note name from A to G"
+        );
+        assert_eq!(
+            parse("Cx").unwrap_err().to_string(),
+            "\"x\" is not a known chord quality"
+        );
+        assert_eq!(
+            parse("C/X").unwrap_err().to_string(),
+            "bass note \"X\" is not a note name from A to G"
+        );
+    }
+
+    fn voiced(symbol: &str, octave: Option<u8>, inversion: Option<u8>, spread: Spread) -> Vec<u8> {
+        let voicing = Voicing {
+            octave,
+            inversion,
+            spread,
+        };
+        parse(symbol).unwrap().voice(&voicing).unwrap()
+    }
+
+    #[test]
+    fn octaves_and_inversions() {
+        use Spread::Close;
+        assert_eq!(voiced("Am", None, None, Close), vec![69, 72, 76]);
+        assert_eq!(voiced("Am", Some(3), None, Close), vec![57, 60, 64]);
+        assert_eq!(voiced("C", None, Some(1), Close), vec![64, 67, 72]);
+        assert_eq!(voiced("C", Some(2), Some(2), Close), vec![43, 48, 52]);
+        assert_eq!(voiced("C7", None, Some(3), Close), vec![70, 72, 76, 79]);
+        assert_eq!(voiced("Cb", Some(0), None, Close), vec![11, 15, 18]);
+        // The inversion can also be written in the symbol.
+        assert_eq!(notes("Am/2"), vec![76, 81, 84]);
+        assert_eq!(notes("C/0"), vec![60, 64, 67]);
+        assert_eq!(voiced("Am/1", Some(3), None, Close), vec![60, 64, 69]);
+    }
+
+    #[test]
+    fn spreads() {
+        assert_eq!(voiced("C", None, None, Spread::Open), vec![60, 67, 76]);
+        assert_eq!(
+            voiced("Cmaj7", None, None, Spread::Open),
+            vec![60, 67, 76, 83]
+        );
+        assert_eq!(voiced("C", None, None, Spread::Drop2), vec![52, 60, 67]);
+        assert_eq!(
+            voiced("Cmaj7", None, None, Spread::Drop2),
+            vec![55, 60, 64, 71]
+        );
+        assert_eq!(
+            voiced("Cmaj7", Some(3), Some(1), Spread::Drop2),
+            vec![47, 52, 55, 60]
+        );
+    }
+
+    #[test]
+    fn slash_bass_stays_below_the_voicing() {
+        use Spread::Close;
+        assert_eq!(voiced("C/E", None, Some(1), Close), vec![52, 64, 67, 72]);
+        assert_eq!(voiced("C/G", Some(2), None, Close), vec![31, 36, 40, 43]);
+        assert_eq!(
+            voiced("C/E", None, None, Spread::Drop2),
+            vec![40, 52, 60, 67]
+        );
+    }
+
+    #[test]
+    fn invalid_voicings() {
+        let voice = |symbol: &str, voicing: Voicing| parse(symbol).unwrap().voice(&voicing);
+        assert_eq!(
+            voice(
+                "Am/1",
+                Voicing {
+                    inversion: Some(2),
+                    ..Voicing::default()
+                }
+            ),
+            Err(VoicingError::ConflictingInversion)
+        );
+        assert_eq!(
+            voice(
+                "C",
+                Voicing {
+                    inversion: Some(3),
+                    ..Voicing::default()
+                }
+            ),
+            Err(VoicingError::InversionTooHigh {
+                inversion: 3,
+                notes: 3
+            })
+        );
+        assert_eq!(
+            voice(
+                "C13",
+                Voicing {
+                    octave: Some(9),
+                    ..Voicing::default()
+                }
+            ),
+            Err(VoicingError::OutOfRange { number: 130 })
+        );
+        assert_eq!(
+            voice(
+                "Cb/Cb",
+                Voicing {
+                    octave: Some(0),
+                    ..Voicing::default()
+                }
+            ),
+            Err(VoicingError::OutOfRange { number: -1 })
+        );
+        assert_eq!(
+            parse("C/3"),
+            Err(ChordError::InversionTooHigh {
+                inversion: 3,
+                notes: 3
+            })
+        );
+        assert_eq!(
+            parse("C/3").unwrap_err().to_string(),
+            "inversion 3 needs at least 4 chord notes, but there are 3"
+        );
+        assert!(matches!(
+            parse("C/300"),
+            Err(ChordError::InversionTooHigh { .. })
+        ));
+    }
+
+    #[test]
+    fn voice_leading_moves_as_little_as_possible() {
+        let range = (48, 84);
+        let mut previous = notes("C");
+        let mut led = vec![];
+        for symbol in ["Am", "F", "G", "C"] {
+            previous =
+                lead_voices(&parse(symbol).unwrap(), Spread::Close, &previous, range).unwrap();
+            led.push(previous.clone());
+        }
+        assert_eq!(
+            led,
+            vec![
+                vec![60, 64, 69],
+                vec![60, 65, 69],
+                vec![59, 62, 67],
+                vec![60, 64, 67],
+            ]
+        );
+        assert_eq!(movement(&[60, 64, 67], &[60, 64, 69]), 4);
+    }
+
+    #[test]
+    fn voice_leading_stays_in_range() {
+        let am = parse("Am").unwrap();
+        let c = notes("C");
+        assert_eq!(
+            lead_voices(&am, Spread::Close, &c, (62, 84)),
+            Some(vec![64, 69, 72])
+        );
+        assert_eq!(
+            lead_voices(&am, Spread::Close, &c, (36, 59)),
+            Some(vec![48, 52, 57])
+        );
+        assert_eq!(lead_voices(&am, Spread::Close, &c, (60, 62)), None);
+    }
+
+    #[test]
+    fn pitches() {
+        assert_eq!(parse_pitch("C4"), Ok(60));
+        assert_eq!(parse_pitch("C#4"), Ok(61));
+        assert_eq!(parse_pitch("Bb2"), Ok(46));
+        assert_eq!(parse_pitch("E2"), Ok(40));
+        assert_eq!(parse_pitch("A0"), Ok(21));
+        assert_eq!(parse_pitch("Cb0"), Ok(11));
+        assert_eq!(parse_pitch("B#3"), Ok(60));
+        assert_eq!(parse_pitch("G9"), Ok(127));
+        assert_eq!(parse_pitch("C-1"), Ok(0));
+        assert!(matches!(
+            parse_pitch("B-2"),
+            Err(PitchError::OutOfRange { number: -1, .. })
+        ));
+        assert_eq!(
+            parse_pitch("C10"),
+            Err(PitchError::OutOfRange {
+                pitch: "C10".to_string(),
+                number: 132
+            })
+        );
+        assert_eq!(
+            parse_pitch("G#9").unwrap_err().to_string(),
+            "\"G#9\" is MIDI note 128, outside of the MIDI range 0 to 127"
+        );
+        assert!(matches!(
+            parse_pitch("H4"),
+            Err(PitchError::InvalidName { .. })
+        ));
+        assert!(matches!(
+            parse_pitch("C"),
+            Err(PitchError::InvalidOctave { .. })
+        ));
+        assert!(matches!(
+            parse_pitch("Cm4"),
+            Err(PitchError::InvalidOctave { .. })
+        ));
+        assert!(matches!(
+            parse_pitch("C+4"),
+            Err(PitchError::InvalidOctave { .. })
+        ));
+        assert!(matches!(
+            parse_pitch("C99999999999"),
+            Err(PitchError::InvalidOctave { .. })
+        ));
+    }
+
+    #[test]
+    fn transposed_chords() {
+        let transposed = |symbol: &str, semitones: i32| {
+            parse(symbol)
+                .unwrap()
+                .transposed(semitones)
+                .map(|chord| chord.notes())
+        };
+        assert_eq!(transposed("Am", 5), Ok(vec![74, 77, 81]));
+        assert_eq!(transposed("C/E", -12), Ok(vec![40, 48, 52, 55]));
+        assert_eq!(
+            transposed("C", -61),
+            Err(VoicingError::OutOfRange { number: -1 })
+        );
+        assert_eq!(
+            transposed("C", i32::MAX),
+            Err(VoicingError::OutOfRange { number: i32::MAX })
+        );
+    }
+
+    #[test]
+    fn pitch_names() {
+        assert_eq!(pitch_name(60), "C4");
+        assert_eq!(pitch_name(70), "A#4");
+        assert_eq!(pitch_name(0), "C-1");
+        assert_eq!(pitch_name(127), "G9");
+        for number in 0..=127 {
+            assert_eq!(parse_pitch(&pitch_name(number)), Ok(number));
+        }
+    }
+
+    #[test]
+    fn note_names() {
+        assert_eq!(split_note_name("C#4"), Some((1, "4")));
+        assert_eq!(split_note_name("Bb"), Some((10, "")));
+        assert_eq!(split_note_name("Cb2"), Some((-1, "2")));
+        assert_eq!(split_note_name("Am7"), Some((9, "m7")));
+        assert_eq!(split_note_name("h"), None);
+        assert_eq!(split_note_name(""), None);
+    }
+}
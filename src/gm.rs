@@ -0,0 +1,210 @@
+/// General MIDI level 1 program names, indexed by program number.
+pub const PROGRAM_NAMES: [&str; 128] = [
+    "Acoustic Grand Piano",
+    "Bright Acoustic Piano",
+    "Electric Grand Piano",
+    "Honky-tonk Piano",
+    "Electric Piano 1",
+    "Electric Piano 2",
+    "Harpsichord",
+    "Clavinet",
+    "Celesta",
+    "Glockenspiel",
+    "Music Box",
+    "Vibraphone",
+    "Marimba",
+    "Xylophone",
+    "Tubular Bells",
+    "Dulcimer",
+    "Drawbar Organ",
+    "Percussive Organ",
+    "Rock Organ",
+    "Church Organ",
+    "Reed Organ",
+    "Accordion",
+    "Harmonica",
+    "Tango Accordion",
+    "Acoustic Guitar (nylon)",
+    "Acoustic Guitar (steel)",
+    "Electric Guitar (jazz)",
+    "Electric Guitar (clean)",
+    "Electric Guitar (muted)",
+    "Overdriven Guitar",
+    "Distortion Guitar",
+    "Guitar Harmonics",
+    "Acoustic Bass",
+    "Electric Bass (finger)",
+    "Electric Bass (pick)",
+    "Fretless Bass",
+    "Slap Bass 1",
+    "Slap Bass 2",
+    "Synth Bass 1",
+    "Synth Bass 2",
+    "Violin",
+    "Viola",
+    "Cello",
+    "Contrabass",
+    "Tremolo Strings",
+    "Pizzicato Strings",
+    "Orchestral Harp",
+    "Timpani",
+    "String Ensemble 1",
+    "String Ensemble 2",
+    "Synth Strings 1",
+    "Synth Strings 2",
+    "Choir Aahs",
+    "Voice Oohs",
+    "Synth Voice",
+    "Orchestra Hit",
+    "Trumpet",
+    "Trombone",
+    "Tuba",
+    "Muted Trumpet",
+    "French Horn",
+    "Brass Section",
+    "Synth Brass 1",
+    "Synth Brass 2",
+    "Soprano Sax",
+    "Alto Sax",
+    "Tenor Sax",
+    "Baritone Sax",
+    "Oboe",
+    "English Horn",
+    "Bassoon",
+    "Clarinet",
+    "Piccolo",
+    "Flute",
+    "Recorder",
+    "Pan Flute",
+    "Blown Bottle",
+    "Shakuhachi",
+    "Whistle",
+    "Ocarina",
+    "Lead 1 (square)",
+    "Lead 2 (sawtooth)",
+    "Lead 3 (calliope)",
+    "Lead 4 (chiff)",
+    "Lead 5 (charang)",
+    "Lead 6 (voice)",
+    "Lead 7 (fifths)",
+    "Lead 8 (bass + lead)",
+    "Pad 1 (new age)",
+    "Pad 2 (warm)",
+    "Pad 3 (polysynth)",
+    "Pad 4 (choir)",
+    "Pad 5 (bowed)",
+    "Pad 6 (metallic)",
+    "Pad 7 (halo)",
+    "Pad 8 (sweep)",
+    "FX 1 (rain)",
+    "FX 2 (soundtrack)",
+    "FX 3 (crystal)",
+    "FX 4 (atmosphere)",
+    "FX 5 (brightness)",
+    "FX 6 (goblins)",
+    "FX 7 (echoes)",
+    "FX 8 (sci-fi)",
+    "Sitar",
+    "Banjo",
+    "Shamisen",
+    "Koto",
+    "Kalimba",
+    "Bagpipe",
+    "Fiddle",
+    "Shanai",
+    "Tinkle Bell",
+    "Agogo",
+    "Steel Drums",
+    "Woodblock",
+    "Taiko Drum",
+    "Melodic Tom",
+    "Synth Drum",
+    "Reverse Cymbal",
+    "Guitar Fret Noise",
+    "Breath Noise",
+    "Seashore",
+    "Bird Tweet",
+    "Telephone Ring",
+    "Helicopter",
+    "Applause",
+    "Gunshot",
+];
+
+/// Short names for common instrument families, mapped to a representative
+/// program of the family.
+const FAMILY_ALIASES: [(&str, u8); 10] = [
+    ("piano", 0),
+    ("organ", 16),
+    ("guitar", 24),
+    ("bass", 32),
+    ("strings", 48),
+    ("choir", 52),
+    ("brass", 61),
+    ("sax", 65),
+    ("lead", 80),
+    ("pad", 88),
+];
+
+/// Lowercases and drops everything but letters and digits, so that
+/// `AcousticGrandPiano`, `acoustic_grand_piano` and "Acoustic Grand Piano"
+/// all compare equal.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Resolves an instrument `type` to a General MIDI program number. The type
+/// is either the program number itself (0-127), a GM program name in any
+/// casing or separator style, or one of a few family names such as `Strings`.
+pub fn program_for(type_: &str) -> Option<u8> {
+    if let Ok(number) = type_.parse::<u8>() {
+        return (number < 128).then_some(number);
+    }
+
+    let wanted = normalize(type_);
+    if let Some(index) = PROGRAM_NAMES
+        .iter()
+        .position(|name| normalize(name) == wanted)
+    {
+        return Some(index as u8);
+    }
+    FAMILY_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == wanted)
+        .map(|(_, program)| *program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn program_by_number() {
+        assert_eq!(program_for("0"), Some(0));
+        assert_eq!(program_for("127"), Some(127));
+        assert_eq!(program_for("128"), None);
+    }
+
+    #[test]
+    fn program_by_gm_name() {
+        assert_eq!(program_for("AcousticGrandPiano"), Some(0));
+        assert_eq!(program_for("electric_bass_finger"), Some(33));
+        assert_eq!(program_for("Violin"), Some(40));
+        assert_eq!(program_for("Gunshot"), Some(127));
+    }
+
+    #[test]
+    fn program_by_family() {
+        assert_eq!(program_for("Piano"), Some(0));
+        assert_eq!(program_for("Strings"), Some(48));
+        assert_eq!(program_for("guitar"), Some(24));
+    }
+
+    #[test]
+    fn unknown_program() {
+        assert_eq!(program_for("Theremin"), None);
+        assert_eq!(program_for(""), None);
+    }
+}
@@ -1,4 +1,10 @@
+use std::fmt;
+
 use logos::Logos;
+use thiserror::Error;
+
+use crate::span::LineIndex;
+pub use crate::span::Span;
 
 #[derive(Logos, Debug, PartialEq, Clone)]
 pub enum Token {
@@ -18,6 +24,18 @@ pub enum Token {
     MidiPath,
     #[token("return")]
     Return,
+    #[token("uses")]
+    Uses,
+    #[token("tempo")]
+    Tempo,
+    #[token("time_signature")]
+    TimeSignature,
+    #[token("voice_leading")]
+    VoiceLeading,
+    #[token("dynamics")]
+    Dynamics,
+    #[token("repeat")]
+    Repeat,
     //    #[regex(r"[A-G][#b]?[m]?")]
     //    Chord,
     #[regex(r"[0-9]+")]
@@ -34,6 +52,16 @@ pub enum Token {
     RBracket,
     #[token("+")]
     Plus,
+    #[token("-")]
+    Minus,
+    #[token("^")]
+    Caret,
+    #[token("*")]
+    Star,
+    #[token("{")]
+    LBrace,
+    #[token("}")]
+    RBrace,
     #[token("=")]
     Equals,
     #[token(".")]
@@ -42,6 +70,12 @@ pub enum Token {
     Comma,
     #[token("#")]
     Hash,
+    #[token("/")]
+    Slash,
+    #[regex(r"//[^\n]*")]
+    LineComment,
+    #[token("/*", block_comment)]
+    BlockComment,
     #[regex(r"[ \t\n\r\f]+", logos::skip)]
     #[error]
     Error,
@@ -49,16 +83,130 @@ pub enum Token {
     Identifier,
 }
 
-pub fn tokenize(source: &str) -> Vec<(Token, String)> {
-    Token::lexer(source)
-        .spanned()
-        .filter_map(|(tok, span)| {
-            if tok != Token::Error {
-                Some((tok, source[span].to_string()))
+/// Consumes the rest of a `/* ... */` comment. An unterminated comment
+/// swallows the remaining input and is reported as an error.
+fn block_comment(lex: &mut logos::Lexer<Token>) -> bool {
+    match lex.remainder().find("*/") {
+        Some(end) => {
+            lex.bump(end + 2);
+            true
+        }
+        None => {
+            lex.bump(lex.remainder().len());
+            false
+        }
+    }
+}
+
+impl Token {
+    /// Tokens that carry no meaning for the parser but are kept around so
+    /// tools such as a formatter can reproduce them.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, Token::LineComment | Token::BlockComment)
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Token::Instrument => "`Instrument`",
+            Token::Pattern => "`Pattern`",
+            Token::Section => "`Section`",
+            Token::Song => "`Song`",
+            Token::Channel => "`Channel`",
+            Token::Type => "`type`",
+            Token::MidiPath => "`midi_path`",
+            Token::Return => "`return`",
+            Token::Uses => "`uses`",
+            Token::Tempo => "`tempo`",
+            Token::TimeSignature => "`time_signature`",
+            Token::VoiceLeading => "`voice_leading`",
+            Token::Dynamics => "`dynamics`",
+            Token::Repeat => "`repeat`",
+            Token::Number => "number",
+            Token::Colon => "`:`",
+            Token::LParen => "`(`",
+            Token::RParen => "`)`",
+            Token::LBracket => "`[`",
+            Token::RBracket => "`]`",
+            Token::Plus => "`+`",
+            Token::Minus => "`-`",
+            Token::Caret => "`^`",
+            Token::Star => "`*`",
+            Token::LBrace => "`{`",
+            Token::RBrace => "`}`",
+            Token::Equals => "`=`",
+            Token::Dot => "`.`",
+            Token::Comma => "`,`",
+            Token::Hash => "`#`",
+            Token::Slash => "`/`",
+            Token::LineComment => "line comment",
+            Token::BlockComment => "block comment",
+            Token::Error => "invalid character",
+            Token::Identifier => "identifier",
+        };
+        f.write_str(text)
+    }
+}
+
+/// A problem found while splitting the source into tokens.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum LexError {
+    #[error("unexpected character {text:?}")]
+    UnexpectedCharacter { text: String, span: Span },
+    #[error("unterminated block comment")]
+    UnterminatedComment { span: Span },
+}
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::UnexpectedCharacter { span, .. } | LexError::UnterminatedComment { span } => {
+                *span
+            }
+        }
+    }
+}
+
+/// Result of lexing a source file: the tokens the parser consumes, the
+/// comments that were taken out of them, and every lexical error. Characters
+/// that caused an error are not part of `tokens`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lexed {
+    pub tokens: Vec<(Token, String, Span)>,
+    pub trivia: Vec<(Token, String, Span)>,
+    pub errors: Vec<LexError>,
+}
+
+pub fn lex(source: &str) -> Lexed {
+    let lines = LineIndex::new(source);
+    let mut tokens = vec![];
+    let mut trivia = vec![];
+    let mut errors = vec![];
+    for (tok, span) in Token::lexer(source).spanned() {
+        let text = source[span.clone()].to_string();
+        let span = lines.span(span);
+        if tok == Token::Error {
+            if text.starts_with("/*") {
+                errors.push(LexError::UnterminatedComment { span });
             } else {
-                None
+                errors.push(LexError::UnexpectedCharacter { text, span });
             }
-        })
-        .collect()
+        } else if tok.is_trivia() {
+            trivia.push((tok, text, span));
+        } else {
+            tokens.push((tok, text, span));
+        }
+    }
+    Lexed {
+        tokens,
+        trivia,
+        errors,
+    }
 }
 
+/// Tokenizes `source`, dropping comments and silently skipping characters
+/// that are not part of the language. Use [`lex`] to get those reported.
+pub fn tokenize(source: &str) -> Vec<(Token, String, Span)> {
+    lex(source).tokens
+}
@@ -1,6 +1,11 @@
 pub mod ast;
+pub mod chord;
+pub mod gm;
 pub mod lexer;
 pub mod midigen;
 pub mod parser;
 pub mod semantic;
 pub mod soundgen;
+pub mod span;
+pub mod timing;
+pub mod transform;
@@ -1,22 +1,92 @@
-use anyhow::Error;
+use anyhow::{Error, anyhow};
 use midly::{
-    Format, Header, MetaMessage, MidiMessage, Smf, Timing, Track, TrackEvent, TrackEventKind,
-    num::{u24, u28},
+    Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind, num::u24,
 };
-use std::{char::MAX, fs::File};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
 
 use crate::ast::*; // assuming this includes your parsed AST types
+use crate::chord;
+use crate::gm;
+use crate::timing::{Beats, TICKS_PER_BEAT};
+use crate::transform;
 use midly::num::{u4, u7};
 use std::collections::HashMap;
 
-const MAX_NUMBER_OF_CHANNELS: u8 = 16;
+/// MIDI ports a song can spread its channels over.
+pub const MAX_PORTS: usize = 128;
+/// General MIDI plays channel 10 (9 counting from zero) as percussion, so it
+/// is never handed out.
+const PERCUSSION_CHANNEL: u8 = 9;
+/// Channels handed out on every port.
+const CHANNELS_PER_PORT: usize = 15;
+/// Most differently named channels a song can use.
+pub const MAX_CHANNELS: usize = MAX_PORTS * CHANNELS_PER_PORT;
+
+/// Notes smooth voice leading keeps chords between when no range is given,
+/// from C3 to C6.
+const DEFAULT_VOICE_RANGE: (u8, u8) = (48, 84);
+
+/// Velocity of notes that neither the pattern nor the section gives
+/// dynamics.
+const DEFAULT_VELOCITY: u8 = 100;
+
+/// Tempo used when a song does not set one, in beats per minute.
+pub const DEFAULT_TEMPO: u16 = 120;
+/// Lowest tempo accepted by semantic analysis, in beats per minute.
+pub const MIN_TEMPO: u16 = 10;
+/// Highest tempo accepted by semantic analysis, in beats per minute.
+pub const MAX_TEMPO: u16 = 1000;
 
 pub struct MidiGen {
     songs: HashMap<String, Song>,
     sections: HashMap<String, Section>,
     instruments: HashMap<String, Instrument>,
     patterns: HashMap<String, Pattern>,
-    time: u32,
+}
+
+/// Everything a song plays, at exact positions from the start of the song,
+/// before it is split into MIDI tracks.
+struct Timeline<'a> {
+    tempos: Vec<(Beats, u16)>,
+    meters: Vec<(Beats, TimeSignature)>,
+    /// One track per channel name, in the order the song first uses them.
+    tracks: Vec<ChannelTrack<'a>>,
+}
+
+/// The events of every `Channel` of a song that shares one name.
+struct ChannelTrack<'a> {
+    name: &'a str,
+    /// MIDI port and channel the track plays on.
+    port: u8,
+    channel: u8,
+    events: Vec<(Beats, TrackEventKind<'a>)>,
+    /// Notes of the chord played last, which smooth voice leading moves
+    /// away from.
+    last_chord: Vec<u8>,
+    /// General MIDI program the channel was last switched to.
+    program: Option<u8>,
+}
+
+impl<'a> Timeline<'a> {
+    /// Index of the track of the channels named `name`, which is added the
+    /// first time the name comes up.
+    fn track(&mut self, name: &'a str) -> usize {
+        if let Some(index) = self.tracks.iter().position(|track| track.name == name) {
+            return index;
+        }
+        let (port, channel) = channel_slot(self.tracks.len());
+        self.tracks.push(ChannelTrack {
+            name,
+            port,
+            channel,
+            events: Vec::new(),
+            last_chord: Vec::new(),
+            program: None,
+        });
+        self.tracks.len() - 1
+    }
 }
 
 impl MidiGen {
@@ -43,213 +113,653 @@ impl MidiGen {
             }
         }
         MidiGen {
-            songs: songs,
-            sections: sections,
-            instruments: instruments,
-            patterns: patterns,
-            time: 0u32,
+            songs,
+            sections,
+            instruments,
+            patterns,
         }
     }
-    pub fn generate(&mut self) -> Vec<String> {
-        let mut song_names = Vec::new();
-        let songs = self.songs.clone();
-        for song in songs.iter() {
-            let (name, _) = song;
-            self.time = 0u32;
-            let file_name = self.generate_song(name).unwrap();
-            song_names.push(file_name);
+    /// Writes every song to `{song}.mid` in the working directory and
+    /// returns the file names.
+    pub fn generate(&self) -> Result<Vec<String>, Error> {
+        self.generate_in(Path::new(""))
+    }
+
+    /// Writes every song to `{song}.mid` in `dir` and returns the paths of
+    /// the files, in song name order. A song is rendered before its file is
+    /// created, so one that cannot be rendered leaves no file behind.
+    pub fn generate_in(&self, dir: &Path) -> Result<Vec<String>, Error> {
+        let mut song_names: Vec<&String> = self.songs.keys().collect();
+        song_names.sort();
+
+        let mut file_names = Vec::new();
+        for name in song_names {
+            let path = dir.join(format!("{}.mid", name));
+            let mut bytes = Vec::new();
+            self.write_song_to(name, &mut bytes)?;
+            fs::write(&path, bytes)?;
+            file_names.push(path.to_string_lossy().into_owned());
         }
+        Ok(file_names)
+    }
 
-        song_names
+    /// Builds the standard MIDI file of a song without writing it anywhere.
+    pub fn render_song(&self, song_name: &str) -> Result<Smf<'_>, Error> {
+        let song = self
+            .songs
+            .get(song_name)
+            .ok_or_else(|| anyhow!("there is no song named {:?}", song_name))?;
+        Ok(Smf {
+            header: Header {
+                format: Format::Parallel,
+                timing: Timing::Metrical((TICKS_PER_BEAT as u16).into()),
+            },
+            tracks: self.song_tracks(song)?,
+        })
     }
 
-    fn generate_song(&mut self, song_name: &String) -> Result<String, Error> {
-        let mut tracks: Vec<Vec<TrackEvent>> = vec![Vec::new(); MAX_NUMBER_OF_CHANNELS.into()];
-        for chan_i in 0..MAX_NUMBER_OF_CHANNELS {
-            let mut track = Vec::new();
+    /// Writes the standard MIDI file of a song to `out`.
+    pub fn write_song_to(&self, song_name: &str, out: &mut impl Write) -> Result<(), Error> {
+        self.render_song(song_name)?.write_std(out)?;
+        Ok(())
+    }
 
-            // This means BPM 120
-            let tempo: u24 = 500000.into();
-            // Add tempo meta
-            track.push(TrackEvent {
-                delta: 0.into(),
-                kind: TrackEventKind::Meta(MetaMessage::Tempo(tempo)), // 120 BPM
-            });
-            tracks.push(track);
+    /// The conductor track followed by one track per channel name the song
+    /// uses.
+    fn song_tracks<'a>(&'a self, song: &'a Song) -> Result<Vec<Vec<TrackEvent<'a>>>, Error> {
+        let timeline = self.schedule_song(song)?;
+        let mut tracks = vec![conductor_track(
+            &song.name,
+            timeline.tempos,
+            timeline.meters,
+        )?];
+        for track in timeline.tracks {
+            tracks.push(channel_track(track)?);
         }
+        Ok(tracks)
+    }
+
+    /// Lays out the song's sections one after another. Each section starts
+    /// with the song's tempo and meter unless it overrides them.
+    fn schedule_song<'a>(&'a self, song: &Song) -> Result<Timeline<'a>, Error> {
+        let song_tempo = song.tempo.map_or(DEFAULT_TEMPO, |(bpm, _)| bpm);
+        let song_meter = song
+            .time_signature
+            .map_or(TimeSignature::COMMON, |(meter, _)| meter);
+        let mut timeline = Timeline {
+            tempos: vec![(Beats::ZERO, song_tempo)],
+            meters: vec![(Beats::ZERO, song_meter)],
+            tracks: Vec::new(),
+        };
 
-        let section_refs: Vec<_> = self.songs.get(song_name).unwrap().entry_sections.clone();
+        play_sequence(&song.entry_sections, Beats::ZERO, &mut |call, position| {
+            let Some(section) = self.sections.get(&call.name) else {
+                return Ok(position);
+            };
+            timeline
+                .tempos
+                .push((position, section.tempo.map_or(song_tempo, |(bpm, _)| bpm)));
+            let meter = section
+                .time_signature
+                .map_or(song_meter, |(meter, _)| meter);
+            timeline.meters.push((position, meter));
+            self.schedule_section(section, call, position, meter, &mut timeline)
+        })?;
+        Ok(timeline)
+    }
 
-        for section_name in section_refs {
-            tracks = self.generate_section(&section_name, tracks)
+    /// Plays every channel of a section as `section_call` asks from `start`
+    /// in `meter`, each channel's patterns back to back. Returns where the
+    /// section ends, which is after its longest channel.
+    fn schedule_section<'a>(
+        &'a self,
+        section: &'a Section,
+        section_call: &SectionCall,
+        start: Beats,
+        meter: TimeSignature,
+        timeline: &mut Timeline<'a>,
+    ) -> Result<Beats, Error> {
+        let mut end = start;
+        for channel in &section.channels {
+            let track = timeline.track(&channel.name);
+            // Sections only switch the program when the instrument changes.
+            if let Some(program) = self.channel_program(channel)
+                && timeline.tracks[track].program != Some(program)
+            {
+                let midi_channel = timeline.tracks[track].channel;
+                timeline.tracks[track].program = Some(program);
+                timeline.tracks[track].events.push((
+                    start,
+                    TrackEventKind::Midi {
+                        channel: u4::new(midi_channel),
+                        message: MidiMessage::ProgramChange {
+                            program: u7::new(program),
+                        },
+                    },
+                ));
+            }
+            let position = play_sequence(&channel.pattern_calls, start, &mut |call, position| {
+                let Some(pattern) = self.patterns.get(&call.name) else {
+                    return Ok(position);
+                };
+                let call_args = forward_arguments(section, &section_call.args, &call.args);
+                let bound = pattern.bind(&call_args);
+                let transforms: Vec<(Transform, Span)> = call
+                    .transforms
+                    .iter()
+                    .chain(&section_call.transforms)
+                    .cloned()
+                    .collect();
+                let (pattern, transpose) =
+                    transform::apply(&bound, &transforms).map_err(|reason| {
+                        anyhow!("pattern {:?} cannot be transformed: {}", call.name, reason)
+                    })?;
+                let pattern = &*pattern;
+                let voice_range = pattern
+                    .voice_leading
+                    .as_ref()
+                    .or(channel.voice_leading.as_ref())
+                    .and_then(|(leading, _)| voice_range(leading));
+                let velocity = pattern
+                    .dynamics
+                    .or(section.dynamics)
+                    .map_or(DEFAULT_VELOCITY, |(dynamic, _)| dynamic.velocity());
+                schedule_pattern(
+                    pattern,
+                    position,
+                    track,
+                    voice_range,
+                    velocity,
+                    transpose,
+                    timeline,
+                )?;
+                let length = pattern
+                    .length_in(meter)
+                    .ok_or_else(|| anyhow!("time signature {} is invalid", meter))?;
+                advance(position, length)
+            })?;
+            end = end.max(position);
         }
+        Ok(end)
+    }
 
-        for chan_i in 0..MAX_NUMBER_OF_CHANNELS {
-            tracks[usize::try_from(chan_i).unwrap()].push(TrackEvent {
-                delta: 0.into(),
-                kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
-            });
+    /// General MIDI program of the instrument a channel uses, if any.
+    fn channel_program(&self, channel: &Channel) -> Option<u8> {
+        let (name, _) = channel.instrument.as_ref()?;
+        let instrument = self.instruments.get(name)?;
+        gm::program_for(&instrument.type_)
+    }
+}
+
+/// Plays the calls of `sequence` back to back from `start` with `play`,
+/// which returns where each call ends. Repeats whose body takes no time are
+/// played once, as playing them again would add nothing.
+fn play_sequence<T>(
+    sequence: &[Sequence<T>],
+    start: Beats,
+    play: &mut impl FnMut(&T, Beats) -> Result<Beats, Error>,
+) -> Result<Beats, Error> {
+    let mut position = start;
+    for item in sequence {
+        match item {
+            Sequence::Call(call) => position = play(call, position)?,
+            Sequence::Repeat { count, body, .. } => {
+                for _ in 0..*count {
+                    let end = play_sequence(body, position, play)?;
+                    if end == position {
+                        break;
+                    }
+                    position = end;
+                }
+            }
         }
-        let smf = Smf {
-            header: Header {
-                format: Format::Parallel,
-                timing: Timing::Metrical(480.into()),
-            },
-            tracks: tracks,
-        };
-        let file_name = format!("{}.mid", song_name);
+    }
+    Ok(position)
+}
+
+/// Tick `position` falls on, or an error once it is past the end of what a
+/// MIDI file can hold.
+fn ticks(position: Beats) -> Result<u32, Error> {
+    position
+        .to_ticks()
+        .ok_or_else(|| anyhow!("the song lasts longer than a MIDI file can hold"))
+}
+
+/// `position` moved on by `length`, checked to still fit in a MIDI file so
+/// that a song repeated too often stops as soon as it runs out of ticks.
+fn advance(position: Beats, length: Beats) -> Result<Beats, Error> {
+    let end = position
+        .checked_add(length)
+        .unwrap_or(Beats::new(u64::MAX, 1));
+    ticks(end)?;
+    Ok(end)
+}
 
-        let mut out = File::create(file_name.clone())?;
-        smf.write_std(&mut out)?;
+/// Port and MIDI channel of the `index`th channel name a song uses. Ports
+/// are filled one after another, skipping the percussion channel.
+fn channel_slot(index: usize) -> (u8, u8) {
+    assert!(
+        index < MAX_CHANNELS,
+        "song uses more than {MAX_CHANNELS} channels"
+    );
+    let port = (index / CHANNELS_PER_PORT) as u8;
+    let channel = (index % CHANNELS_PER_PORT) as u8;
+    if channel < PERCUSSION_CHANNEL {
+        (port, channel)
+    } else {
+        (port, channel + 1)
+    }
+}
 
-        Ok(file_name)
+/// Lowest and highest note smooth voice leading may use, or `None` when
+/// chords are voiced as written.
+pub(crate) fn voice_range(leading: &VoiceLeading) -> Option<(u8, u8)> {
+    match leading {
+        VoiceLeading::None => None,
+        VoiceLeading::Smooth { range: None } => Some(DEFAULT_VOICE_RANGE),
+        VoiceLeading::Smooth {
+            range: Some((low, high)),
+        } => Some(
+            chord::parse_pitch(low)
+                .and_then(|low| Ok((low, chord::parse_pitch(high)?)))
+                .unwrap_or(DEFAULT_VOICE_RANGE),
+        ),
     }
+}
 
-    fn generate_section<'a>(
-        &mut self,
-        section_name: &String,
-        mut tracks: Vec<Vec<TrackEvent<'a>>>,
-    ) -> Vec<Vec<TrackEvent<'a>>> {
-        let section = self.sections.get(section_name).unwrap().clone();
+/// The arguments of a pattern call in `section`, with every one that names
+/// a parameter of the section replaced by the argument the section was
+/// called with.
+fn forward_arguments(
+    section: &Section,
+    section_args: &[(Argument, Span)],
+    call_args: &[(Argument, Span)],
+) -> Vec<(Argument, Span)> {
+    call_args
+        .iter()
+        .map(|(arg, span)| {
+            if let Argument::Symbol(symbol) = arg
+                && let Some(index) = section.params.iter().position(|(param, _)| param == symbol)
+                && let Some(forwarded) = section_args.get(index)
+            {
+                forwarded.clone()
+            } else {
+                (arg.clone(), *span)
+            }
+        })
+        .collect()
+}
 
-        for i in 0..section.channels.len() {
-            let channel = section.channels.get(i).unwrap();
-            let mut time = 0u32;
-            for pattern in channel.pattern_calls.iter() {
-                let result = self.generate_pattern(pattern, i, tracks);
-                time = self.time.max(result.0);
-                tracks = result.1;
+/// Velocity of a note starting on `step` of `pattern` without a `vel` of
+/// its own. Under a hairpin the velocity moves in a straight line from the
+/// first note the hairpin covers to the last one; elsewhere it is `base`.
+fn dynamic_velocity(pattern: &Pattern, step: u8, base: u8) -> u8 {
+    let note_starts = || {
+        pattern.events.iter().filter_map(|event| match event {
+            PatternEvent::Note { duration, .. } | PatternEvent::Pitch { duration, .. } => {
+                Some(duration.0)
             }
-            self.time = time;
+            _ => None,
+        })
+    };
+    let hairpin = pattern.events.iter().find_map(|event| match event {
+        PatternEvent::Hairpin {
+            from,
+            to,
+            duration: (first, last),
+            ..
+        } if (*first..=*last).contains(&step) => {
+            let covered = note_starts().filter(|start| (*first..=*last).contains(start));
+            let first_note = covered.clone().min()?;
+            let last_note = covered.max()?;
+            Some((
+                from.map_or(base, |from| from.velocity()),
+                to.velocity(),
+                first_note,
+                last_note,
+            ))
         }
+        _ => None,
+    });
+    let Some((from, to, first_note, last_note)) = hairpin else {
+        return base;
+    };
+    if first_note == last_note {
+        return to;
+    }
+    let span = i32::from(last_note - first_note);
+    let offset = i32::from(step - first_note);
+    let (from, to) = (i32::from(from), i32::from(to));
+    // Round to the nearest velocity, halves away from `from`.
+    let change = (to - from) * offset;
+    let rounded = (2 * change + change.signum() * span) / (2 * span);
+    (from + rounded) as u8
+}
 
-        tracks
-    }
-    fn generate_pattern<'a>(
-        &mut self,
-        pattern_name: &String,
-        channel: usize,
-        mut tracks: Vec<Vec<TrackEvent<'a>>>,
-    ) -> (u32, Vec<Vec<TrackEvent<'a>>>) {
-        let pattern = self.patterns.get(pattern_name).unwrap().clone();
-        let mut time = self.time.clone();
-
-        for event in pattern.events.iter() {
-            match event {
-                PatternEvent::Note { chord, duration } => {
-                    let (start, end) = duration;
-                    let duration = 120u32 * (u32::from(*end) - u32::from(*start));
-
-                    let start_time = time + u32::from(*start);
-                    let end_time = time + u32::from(*end);
-
-                    let chord_events = chord_to_midi_events(
-                        &chord,
-                        start_time,
-                        duration,
-                        100,
-                        channel.try_into().unwrap(),
-                    );
-                    tracks.get_mut(channel).unwrap().extend(chord_events);
-                    time += end_time;
+/// Places the events of one pattern call that starts at `start`. Tempo
+/// changes go to the conductor track. With a `voice_range`, chords that do
+/// not set their own octave or inversion are voice led. Notes without a
+/// velocity of their own are played at `velocity`, shaped by the pattern's
+/// hairpins. Chords are moved by `transpose` semitones. Fails on a chord or
+/// pitch that cannot be played.
+fn schedule_pattern(
+    pattern: &Pattern,
+    start: Beats,
+    track: usize,
+    voice_range: Option<(u8, u8)>,
+    velocity: u8,
+    transpose: i32,
+    timeline: &mut Timeline,
+) -> Result<(), Error> {
+    let ChannelTrack {
+        channel,
+        events,
+        last_chord,
+        ..
+    } = &mut timeline.tracks[track];
+    let channel = *channel;
+    for event in &pattern.events {
+        match event {
+            PatternEvent::Note {
+                chord,
+                voicing,
+                velocity: note_velocity,
+                duration: (first, last),
+                ..
+            } => {
+                let mut notes = chord_notes(&pattern.name, chord, voicing, transpose)?;
+                if let Some(range) = voice_range
+                    && let Ok(parsed) = chord::parse(chord)
+                    && let Ok(parsed) = parsed.transposed(transpose)
+                    && parsed.inversion.is_none()
+                    && voicing.octave.is_none()
+                    && voicing.inversion.is_none()
+                {
+                    let previous = if last_chord.is_empty() {
+                        &notes
+                    } else {
+                        &*last_chord
+                    };
+                    if let Some(led) = chord::lead_voices(&parsed, voicing.spread, previous, range)
+                    {
+                        notes = led;
+                    }
                 }
-                PatternEvent::Wait { duration } => {
-                    let (start, end) = duration;
-                    let duration = 120u32 * (u32::from(*end) - u32::from(*start));
-
-                    let start_time = time + u32::from(*start);
-                    let end_time = time + u32::from(*end);
-
-                    let chord_events = chord_to_midi_events(
-                        "Am",
-                        start_time,
-                        duration,
-                        0,
-                        channel.try_into().unwrap(),
-                    );
-                    tracks.get_mut(channel).unwrap().extend(chord_events);
-                    time += end_time;
+                if !notes.is_empty() {
+                    *last_chord = notes.clone();
                 }
+                events.extend(note_events(
+                    &notes,
+                    start + Beats::step_start(*first),
+                    start + Beats::step_end(*last),
+                    note_velocity
+                        .as_ref()
+                        .and_then(Value::literal)
+                        .unwrap_or_else(|| dynamic_velocity(pattern, *first, velocity)),
+                    channel,
+                ));
+            }
+            PatternEvent::Pitch {
+                pitch,
+                velocity: note_velocity,
+                duration: (first, last),
+                ..
+            } => {
+                let note = chord::parse_pitch(pitch).map_err(|reason| {
+                    anyhow!(
+                        "pattern {:?} plays {:?}, which is not a valid pitch: {}",
+                        pattern.name,
+                        pitch,
+                        reason
+                    )
+                })?;
+                let notes = [note];
+                events.extend(note_events(
+                    &notes,
+                    start + Beats::step_start(*first),
+                    start + Beats::step_end(*last),
+                    note_velocity
+                        .as_ref()
+                        .and_then(Value::literal)
+                        .unwrap_or_else(|| dynamic_velocity(pattern, *first, velocity)),
+                    channel,
+                ));
+            }
+            PatternEvent::Tempo {
+                bpm,
+                duration: (first, _),
+                ..
+            } => {
+                timeline
+                    .tempos
+                    .push((start + Beats::step_start(*first), *bpm));
             }
+            // Rests only take up time, which `Pattern::length_in` accounts for.
+            PatternEvent::Wait { .. } | PatternEvent::Hairpin { .. } => {}
         }
-        (time, tracks)
     }
+    Ok(())
 }
 
-pub fn chord_to_midi_events(
-    chord: &str,
-    start_time: u32,
-    duration: u32,
-    velocity: u8,
-    channel: u8,
-) -> Vec<TrackEvent<'static>> {
-    let mut notes = match parse_chord(chord) {
-        Some(n) => n,
-        None => return vec![],
+/// Builds the conductor track, named after the song, holding the song's
+/// tempo map and meter.
+fn conductor_track<'a>(
+    name: &'a str,
+    tempos: Vec<(Beats, u16)>,
+    meters: Vec<(Beats, TimeSignature)>,
+) -> Result<Vec<TrackEvent<'a>>, Error> {
+    // Time signatures go before tempo changes on the same tick.
+    let mut events = vec![(0, track_name_message(name))];
+    for (position, meter) in effective_changes(meters) {
+        events.push((ticks(position)?, time_signature_message(meter)?));
+    }
+    for (position, bpm) in effective_changes(tempos) {
+        events.push((ticks(position)?, tempo_message(bpm)?));
+    }
+    events.sort_by_key(|(tick, _)| *tick);
+    Ok(into_track(events))
+}
+
+/// Builds the MIDI track of a channel, named after it and, past the first
+/// port, routed to its port. Where events share a tick, notes are released
+/// before anything else happens and struck last, so a note that is played
+/// again right away is not cut short.
+fn channel_track(track: ChannelTrack<'_>) -> Result<Vec<TrackEvent<'_>>, Error> {
+    let order = |kind: &TrackEventKind| match kind {
+        TrackEventKind::Midi {
+            message: MidiMessage::NoteOff { .. },
+            ..
+        } => 0,
+        TrackEventKind::Midi {
+            message: MidiMessage::NoteOn { .. },
+            ..
+        } => 2,
+        _ => 1,
     };
+    let mut events: Vec<(u32, TrackEventKind)> = track
+        .events
+        .into_iter()
+        .map(|(position, kind)| Ok((ticks(position)?, kind)))
+        .collect::<Result<_, Error>>()?;
+    events.sort_by_key(|(tick, kind)| (*tick, order(kind)));
+    if track.port > 0 {
+        events.insert(
+            0,
+            (
+                0,
+                TrackEventKind::Meta(MetaMessage::MidiPort(u7::new(track.port))),
+            ),
+        );
+    }
+    events.insert(0, (0, track_name_message(track.name)));
+    Ok(into_track(events))
+}
 
-    notes.sort();
-    let mut events = Vec::new();
+/// Orders `(position, value)` changes by position, keeps only the last
+/// change at every position and drops changes that keep the current value.
+fn effective_changes<T: PartialEq>(mut changes: Vec<(Beats, T)>) -> Vec<(Beats, T)> {
+    changes.sort_by_key(|(position, _)| *position);
+    let mut collapsed: Vec<(Beats, T)> = vec![];
+    for (position, value) in changes {
+        match collapsed.last_mut() {
+            Some(last) if last.0 == position => last.1 = value,
+            _ => collapsed.push((position, value)),
+        }
+    }
+    collapsed.dedup_by(|next, previous| next.1 == previous.1);
+    collapsed
+}
+
+fn track_name_message(name: &str) -> TrackEventKind<'_> {
+    TrackEventKind::Meta(MetaMessage::TrackName(name.as_bytes()))
+}
+
+/// Tempo meta message for `bpm`, which has to be a tempo semantic analysis
+/// accepts.
+fn tempo_message(bpm: u16) -> Result<TrackEventKind<'static>, Error> {
+    if !(MIN_TEMPO..=MAX_TEMPO).contains(&bpm) {
+        return Err(anyhow!(
+            "tempo {} is out of range, expected {} to {} BPM",
+            bpm,
+            MIN_TEMPO,
+            MAX_TEMPO
+        ));
+    }
+    Ok(TrackEventKind::Meta(MetaMessage::Tempo(u24::new(
+        60_000_000 / u32::from(bpm),
+    ))))
+}
+
+fn time_signature_message(meter: TimeSignature) -> Result<TrackEventKind<'static>, Error> {
+    if !meter.is_valid() {
+        return Err(anyhow!("time signature {} is invalid", meter));
+    }
+    // MIDI stores the denominator as a power of two, and here uses the
+    // customary 24 clocks per metronome click and 8 32nd notes per quarter.
+    let denominator = meter.denominator.trailing_zeros() as u8;
+    Ok(TrackEventKind::Meta(MetaMessage::TimeSignature(
+        meter.numerator,
+        denominator,
+        24,
+        8,
+    )))
+}
+
+/// Turns events at absolute ticks, already in order, into events with delta
+/// times.
+fn with_deltas(events: Vec<(u32, TrackEventKind<'_>)>) -> Vec<TrackEvent<'_>> {
+    let mut previous = 0;
+    events
+        .into_iter()
+        .map(|(tick, kind)| {
+            let delta = tick - previous;
+            previous = tick;
+            TrackEvent {
+                delta: delta.into(),
+                kind,
+            }
+        })
+        .collect()
+}
+
+/// Turns events at absolute ticks, already in order, into a track closed by
+/// an end of track event.
+fn into_track(events: Vec<(u32, TrackEventKind<'_>)>) -> Vec<TrackEvent<'_>> {
+    let mut track = with_deltas(events);
+    track.push(TrackEvent {
+        delta: 0.into(),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+    track
+}
 
-    // NoteOn events – all with delta = 0 except the first
-    for (i, note) in notes.iter().enumerate() {
-        events.push(TrackEvent {
-            delta: if i == 0 { start_time.into() } else { 0.into() },
-            kind: TrackEventKind::Midi {
+/// Voiced notes of a chord symbol that `pattern` plays, moved by
+/// `transpose` semitones.
+fn chord_notes(
+    pattern: &str,
+    symbol: &str,
+    voicing: &Voicing,
+    transpose: i32,
+) -> Result<Vec<u8>, Error> {
+    let chord = chord::parse(symbol).map_err(|reason| {
+        anyhow!(
+            "pattern {:?} plays {:?}, which is not a valid chord: {}",
+            pattern,
+            symbol,
+            reason
+        )
+    })?;
+    chord
+        .transposed(transpose)
+        .and_then(|chord| chord.voice(voicing))
+        .map_err(|reason| {
+            anyhow!(
+                "pattern {:?} cannot voice {:?}: {}",
+                pattern,
+                symbol,
+                reason
+            )
+        })
+}
+
+/// Strikes `notes` at `start` and releases them at `end`, lowest note first.
+fn note_events<T: Copy>(
+    notes: &[u8],
+    start: T,
+    end: T,
+    velocity: u8,
+    channel: u8,
+) -> Vec<(T, TrackEventKind<'static>)> {
+    let mut notes = notes.to_vec();
+    notes.sort();
+    let note_on = notes.iter().map(|note| {
+        (
+            start,
+            TrackEventKind::Midi {
                 channel: u4::new(channel),
                 message: MidiMessage::NoteOn {
                     key: u7::new(*note),
-                    vel: u7::new(velocity),
+                    vel: u7::new(velocity.min(127)),
                 },
             },
-        });
-    }
-
-    // NoteOff events – all with delta = 0 except the first
-    for (i, note) in notes.iter().enumerate() {
-        events.push(TrackEvent {
-            delta: if i == 0 { duration.into() } else { 0.into() },
-            kind: TrackEventKind::Midi {
+        )
+    });
+    let note_off = notes.iter().map(|note| {
+        (
+            end,
+            TrackEventKind::Midi {
                 channel: u4::new(channel),
                 message: MidiMessage::NoteOff {
                     key: u7::new(*note),
                     vel: u7::new(0),
                 },
             },
-        });
-    }
-
-    events
+        )
+    });
+    note_on.chain(note_off).collect()
 }
 
-fn parse_chord(name: &str) -> Option<Vec<u8>> {
-    let base_notes = HashMap::from([
-        ("C", 60),
-        ("C#", 61),
-        ("D", 62),
-        ("D#", 63),
-        ("E", 64),
-        ("F", 65),
-        ("F#", 66),
-        ("G", 67),
-        ("G#", 68),
-        ("A", 69),
-        ("A#", 70),
-        ("B", 71),
-    ]);
-
-    let (root, is_minor) = if name.ends_with('m') && name.len() > 1 {
-        (&name[..name.len() - 1], true)
-    } else {
-        (name, false)
+/// The events of a single chord starting `start_time` ticks in and lasting
+/// `duration` ticks, with delta times.
+pub fn chord_to_midi_events(
+    chord: &str,
+    start_time: u32,
+    duration: u32,
+    velocity: u8,
+    channel: u8,
+) -> Vec<TrackEvent<'static>> {
+    let notes = match chord::parse(chord) {
+        Ok(chord) => chord.notes(),
+        Err(_) => return vec![],
     };
 
-    let root_midi = *base_notes.get(root)?;
-    Some(if is_minor {
-        vec![root_midi, root_midi + 3, root_midi + 7] // minor triad
-    } else {
-        vec![root_midi, root_midi + 4, root_midi + 7] // major triad
-    })
+    with_deltas(note_events(
+        &notes,
+        start_time,
+        start_time + duration,
+        velocity,
+        channel,
+    ))
 }
 
 #[cfg(test)]
@@ -261,27 +771,60 @@ mod tests {
                 midi_path: "midipath".to_string(),
                 type_: "guitar".to_string(),
                 name: "Piano".to_string(),
+                span: Span::default(),
             }),
             TopLevel::Pattern(Pattern {
                 name: "Pattern1".to_string(),
+                params: vec![],
+                voice_leading: None,
+                dynamics: None,
                 events: vec![
                     PatternEvent::Note {
                         chord: "C".to_string(),
-                        duration: (0, 1),
+                        voicing: Voicing::default(),
+                        velocity: None,
+                        duration: (1, 4),
+                        span: Span::default(),
+                    },
+                    PatternEvent::Wait {
+                        bars: None,
+                        duration: (5, 8),
+                        span: Span::default(),
                     },
-                    PatternEvent::Wait { duration: (1, 2) },
                 ],
+                span: Span::default(),
             }),
             TopLevel::Section(Section {
                 name: "Section1".to_string(),
+                params: vec![],
+                tempo: None,
+                time_signature: None,
+                dynamics: None,
                 channels: vec![Channel {
                     name: "x".to_string(),
-                    pattern_calls: vec!["Pattern1".to_string()],
+                    instrument: Some(("Piano".to_string(), Span::default())),
+                    voice_leading: None,
+                    pattern_calls: vec![Sequence::Call(PatternCall {
+                        name: "Pattern1".to_string(),
+                        args: vec![],
+                        transforms: vec![],
+                        span: Span::default(),
+                    })],
+                    span: Span::default(),
                 }],
+                span: Span::default(),
             }),
             TopLevel::Song(Song {
                 name: "Song1".to_string(),
-                entry_sections: vec!["Section1".to_string()],
+                tempo: None,
+                time_signature: None,
+                entry_sections: vec![Sequence::Call(SectionCall {
+                    name: "Section1".to_string(),
+                    args: vec![],
+                    transforms: vec![],
+                    span: Span::default(),
+                })],
+                span: Span::default(),
             }),
         ]
     }
@@ -318,54 +861,66 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_chord_major() {
-        let notes = parse_chord("C").unwrap();
-        assert_eq!(notes, vec![60, 64, 67]);
-    }
+    fn test_midigen_new() {
+        let ast = create_test_ast();
+        let midigen = MidiGen::new(&ast);
 
-    #[test]
-    fn test_parse_chord_minor() {
-        let notes = parse_chord("Am").unwrap();
-        assert_eq!(notes, vec![69, 72, 76]);
+        assert_eq!(midigen.songs.len(), 1);
+        assert_eq!(midigen.sections.len(), 1);
+        assert_eq!(midigen.instruments.len(), 1);
+        assert_eq!(midigen.patterns.len(), 1);
     }
 
     #[test]
-    fn test_parse_chord_invalid() {
-        let notes = parse_chord("H"); // H is not a valid note
-        assert!(notes.is_none());
+    fn test_midigen_render_song() {
+        let ast = create_test_ast();
+        let midigen = MidiGen::new(&ast);
+
+        let smf = midigen.render_song("Song1").unwrap();
+        assert_eq!(smf.header.timing, Timing::Metrical(480.into()));
+        assert_eq!(
+            smf.tracks,
+            midigen.song_tracks(&midigen.songs["Song1"]).unwrap()
+        );
+
+        let error = midigen.render_song("Song2").unwrap_err();
+        assert_eq!(error.to_string(), "there is no song named \"Song2\"");
     }
 
     #[test]
-    fn test_midigen_new() {
+    fn test_midigen_write_song_to_memory() {
         let ast = create_test_ast();
         let midigen = MidiGen::new(&ast);
 
-        assert_eq!(midigen.songs.len(), 1);
-        assert_eq!(midigen.sections.len(), 1);
-        assert_eq!(midigen.instruments.len(), 1);
-        assert_eq!(midigen.patterns.len(), 1);
+        let mut bytes = Vec::new();
+        midigen.write_song_to("Song1", &mut bytes).unwrap();
+        assert!(bytes.starts_with(b"MThd"));
+
+        let smf = Smf::parse(&bytes).unwrap();
+        assert_eq!(smf, midigen.render_song("Song1").unwrap());
     }
 
     #[test]
-    fn test_midigen_generate_song() {
+    fn test_midigen_generate_in() {
         let ast = create_test_ast();
-        let mut midigen = MidiGen::new(&ast);
-
-        let result = midigen.generate_song(&"Song1".to_string());
-        assert!(result.is_ok());
+        let midigen = MidiGen::new(&ast);
+        let dir = std::env::temp_dir().join(format!("cricket-midigen-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
 
-        let file_name = result.unwrap();
-        assert!(file_name.ends_with(".mid"));
+        let files = midigen.generate_in(&dir).unwrap();
+        let expected = dir.join("Song1.mid");
+        assert_eq!(files, vec![expected.to_string_lossy().into_owned()]);
+        assert!(expected.exists());
 
-        let _ = std::fs::remove_file(file_name);
+        std::fs::remove_dir_all(dir).unwrap();
     }
 
     #[test]
     fn test_midigen_generate() {
         let ast = create_test_ast();
-        let mut midigen = MidiGen::new(&ast);
+        let midigen = MidiGen::new(&ast);
 
-        let songs = midigen.generate();
+        let songs = midigen.generate().unwrap();
         assert_eq!(songs.len(), 1);
 
         let file_name = &songs[0];
@@ -373,4 +928,1007 @@ mod tests {
 
         let _ = std::fs::remove_file(file_name);
     }
+
+    #[test]
+    fn test_generate_section_sets_channel_program() {
+        let ast = create_test_ast();
+        let midigen = MidiGen::new(&ast);
+
+        let tracks = midigen.song_tracks(&midigen.songs["Song1"]).unwrap();
+        assert_eq!(tracks.len(), 2);
+        assert!(matches!(
+            tracks[1][1].kind,
+            TrackEventKind::Midi {
+                message: MidiMessage::ProgramChange { program },
+                ..
+            } if program.as_int() == 24
+        ));
+    }
+
+    fn midigen_for(source: &str) -> MidiGen {
+        let tokens = crate::lexer::tokenize(source);
+        let ast = crate::parser::Parser::new(tokens).parse().unwrap();
+        MidiGen::new(&ast)
+    }
+
+    fn tempo_changes(track: &[TrackEvent]) -> Vec<(u32, u32)> {
+        let mut tick = 0;
+        let mut changes = vec![];
+        for event in track {
+            tick += event.delta.as_int();
+            if let TrackEventKind::Meta(MetaMessage::Tempo(tempo)) = event.kind {
+                changes.push((tick, 60_000_000 / tempo.as_int()));
+            }
+        }
+        changes
+    }
+
+    #[test]
+    fn test_conductor_tempo_map() {
+        let pattern = |name: &str, events| {
+            TopLevel::Pattern(Pattern {
+                name: name.to_string(),
+                params: vec![],
+                voice_leading: None,
+                dynamics: None,
+                events,
+                span: Span::default(),
+            })
+        };
+        let section = |name: &str, tempo, calls: &[&str]| {
+            TopLevel::Section(Section {
+                name: name.to_string(),
+                params: vec![],
+                tempo,
+                time_signature: None,
+                dynamics: None,
+                channels: vec![Channel {
+                    name: "x".to_string(),
+                    instrument: None,
+                    voice_leading: None,
+                    pattern_calls: calls
+                        .iter()
+                        .map(|c| {
+                            Sequence::Call(PatternCall {
+                                name: c.to_string(),
+                                args: vec![],
+                                transforms: vec![],
+                                span: Span::default(),
+                            })
+                        })
+                        .collect(),
+                    span: Span::default(),
+                }],
+                span: Span::default(),
+            })
+        };
+        let ast = vec![
+            pattern(
+                "bar",
+                vec![PatternEvent::Wait {
+                    bars: None,
+                    duration: (1, 16),
+                    span: Span::default(),
+                }],
+            ),
+            pattern(
+                "speedup",
+                vec![
+                    PatternEvent::Wait {
+                        bars: None,
+                        duration: (1, 16),
+                        span: Span::default(),
+                    },
+                    PatternEvent::Tempo {
+                        bpm: 140,
+                        duration: (9, 16),
+                        span: Span::default(),
+                    },
+                ],
+            ),
+            section("Verse", None, &["bar", "speedup"]),
+            section("Chorus", Some((100, Span::default())), &["bar"]),
+            TopLevel::Song(Song {
+                name: "Song1".to_string(),
+                tempo: Some((92, Span::default())),
+                time_signature: None,
+                entry_sections: ["Verse", "Chorus", "Verse"]
+                    .iter()
+                    .map(|s| {
+                        Sequence::Call(SectionCall {
+                            name: s.to_string(),
+                            args: vec![],
+                            transforms: vec![],
+                            span: Span::default(),
+                        })
+                    })
+                    .collect(),
+                span: Span::default(),
+            }),
+        ];
+        let midigen = MidiGen::new(&ast);
+
+        let conductor = &midigen.song_tracks(&midigen.songs["Song1"]).unwrap()[0];
+        assert_eq!(
+            tempo_changes(conductor),
+            vec![
+                (0, 92),
+                // `Tempo(140)` on step 9 of the second bar.
+                (1920 + 960, 140),
+                // Chorus override, then back to the song tempo.
+                (3840, 100),
+                (5760, 92),
+                (5760 + 1920 + 960, 140),
+            ]
+        );
+        assert!(matches!(
+            conductor.last().unwrap().kind,
+            TrackEventKind::Meta(MetaMessage::EndOfTrack)
+        ));
+    }
+
+    #[test]
+    fn test_conductor_time_signatures() {
+        let midigen = midigen_for(
+            r#"
+            Pattern waltz():
+                return [1:12] Note(C)
+
+            Section Verse:
+                Channel lead:
+                    return waltz() + waltz()
+
+            Section Chorus:
+                time_signature: 6/8
+                Channel lead:
+                    return waltz()
+
+            Song HotlineBling:
+                time_signature: 3/4
+                return Verse() + Chorus() + Verse()
+            "#,
+        );
+
+        let conductor = &midigen.song_tracks(&midigen.songs["HotlineBling"]).unwrap()[0];
+        let mut tick = 0;
+        let mut meters = vec![];
+        for event in conductor.iter() {
+            tick += event.delta.as_int();
+            if let TrackEventKind::Meta(MetaMessage::TimeSignature(num, den, _, _)) = event.kind {
+                meters.push((tick, num, 1 << den));
+            }
+        }
+        assert_eq!(meters, vec![(0, 3, 4), (2880, 6, 8), (4320, 3, 4)]);
+        // The track name, then the meter before the tempo at the start of the
+        // song.
+        assert!(matches!(
+            conductor[0].kind,
+            TrackEventKind::Meta(MetaMessage::TrackName(b"HotlineBling"))
+        ));
+        assert!(matches!(
+            conductor[2].kind,
+            TrackEventKind::Meta(MetaMessage::Tempo(_))
+        ));
+    }
+
+    /// `(tick, key)` of every sounding NoteOn and NoteOff in a track, with
+    /// NoteOffs as negative keys.
+    fn note_ticks(track: &[TrackEvent]) -> Vec<(u32, i16)> {
+        let mut tick = 0;
+        let mut notes = vec![];
+        for event in track {
+            tick += event.delta.as_int();
+            match event.kind {
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { key, vel },
+                    ..
+                } if vel > 0 => notes.push((tick, i16::from(key.as_int()))),
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOff { key, .. },
+                    ..
+                } => notes.push((tick, -i16::from(key.as_int()))),
+                _ => {}
+            }
+        }
+        notes
+    }
+
+    #[test]
+    fn test_patterns_play_back_to_back() {
+        let midigen = midigen_for(
+            r#"
+            Pattern first():
+                return [1:4] Note(C) + [5:16] Note(Am)
+
+            Pattern second():
+                return [3:6] Note(B)
+
+            Section Verse:
+                Channel lead:
+                    return first() + second() + first()
+
+            Song HotlineBling:
+                return Verse()
+            "#,
+        );
+
+        let tracks = midigen.song_tracks(&midigen.songs["HotlineBling"]).unwrap();
+        let notes: Vec<_> = note_ticks(&tracks[1])
+            .into_iter()
+            .filter(|(_, key)| [60, 69, 71].contains(&key.abs()))
+            .collect();
+        assert_eq!(
+            notes,
+            vec![
+                (0, 60),
+                (480, -60),
+                (480, 69),
+                (1920, -69),
+                // `second()` starts after the 16 steps of `first()`, and its
+                // steps 3 to 6 are the second and third beat.
+                (1920 + 240, 71),
+                (1920 + 720, -71),
+                // `second()` is 6 steps long.
+                (1920 + 720, 60),
+                (1920 + 720 + 480, -60),
+                (1920 + 720 + 480, 69),
+                (1920 + 720 + 1920, -69),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sections_follow_their_longest_channel() {
+        let midigen = midigen_for(
+            r#"
+            Pattern bar():
+                return [1:16] Note(C)
+
+            Pattern beat():
+                return [1:4] Note(Am)
+
+            Section Verse:
+                Channel lead:
+                    return bar() + bar()
+                Channel bass:
+                    return beat()
+
+            Section Chorus:
+                Channel lead:
+                    return beat() + beat()
+                Channel bass:
+                    return beat() + beat() + beat()
+
+            Song HotlineBling:
+                return Verse() + Chorus() + Verse()
+            "#,
+        );
+
+        let tracks = midigen.song_tracks(&midigen.songs["HotlineBling"]).unwrap();
+        let onsets = |track: &[TrackEvent]| -> Vec<u32> {
+            let mut onsets: Vec<u32> = note_ticks(track)
+                .into_iter()
+                .filter(|(_, key)| *key > 0)
+                .map(|(tick, _)| tick)
+                .collect();
+            onsets.dedup();
+            onsets
+        };
+        // Verse lasts two bars, Chorus three beats.
+        assert_eq!(onsets(&tracks[1]), vec![0, 1920, 3840, 4320, 5280, 7200]);
+        assert_eq!(onsets(&tracks[2]), vec![0, 3840, 4320, 4800, 5280]);
+    }
+
+    #[test]
+    fn test_repeated_notes_are_released_before_they_are_struck_again() {
+        let midigen = midigen_for(
+            r#"
+            Pattern pulse():
+                return [1:4] Note(C) + [5:8] Note(C)
+
+            Section Verse:
+                Channel lead:
+                    return pulse()
+
+            Song HotlineBling:
+                return Verse()
+            "#,
+        );
+
+        let tracks = midigen.song_tracks(&midigen.songs["HotlineBling"]).unwrap();
+        let at_480: Vec<_> = note_ticks(&tracks[1])
+            .into_iter()
+            .filter(|(tick, _)| *tick == 480)
+            .map(|(_, key)| key)
+            .collect();
+        assert_eq!(at_480, vec![-60, -64, -67, 60, 64, 67]);
+    }
+
+    #[test]
+    fn test_one_named_track_per_channel_name() {
+        let midigen = midigen_for(
+            r#"
+            Pattern bar():
+                return [1:16] Note(C)
+
+            Section Verse:
+                Channel lead:
+                    return bar()
+                Channel bass:
+                    return bar()
+
+            Section Chorus:
+                Channel pad:
+                    return bar()
+                Channel lead:
+                    return bar()
+
+            Section Unused:
+                Channel drums:
+                    return bar()
+
+            Song HotlineBling:
+                return Verse() + Chorus()
+            "#,
+        );
+
+        let tracks = midigen.song_tracks(&midigen.songs["HotlineBling"]).unwrap();
+        let summary: Vec<_> = tracks[1..]
+            .iter()
+            .map(|track| {
+                let TrackEventKind::Meta(MetaMessage::TrackName(name)) = track[0].kind else {
+                    panic!("track does not start with its name");
+                };
+                let channels: Vec<u8> = track
+                    .iter()
+                    .filter_map(|event| match event.kind {
+                        TrackEventKind::Midi { channel, .. } => Some(channel.as_int()),
+                        _ => None,
+                    })
+                    .collect();
+                (
+                    std::str::from_utf8(name).unwrap(),
+                    channels[0],
+                    channels.len(),
+                )
+            })
+            .collect();
+        // `lead` plays in both sections on the same track and MIDI channel.
+        assert_eq!(
+            summary,
+            vec![("lead", 0, 12), ("bass", 1, 6), ("pad", 2, 6)]
+        );
+    }
+
+    #[test]
+    fn test_channel_slots_skip_percussion_and_spill_onto_ports() {
+        let slots: Vec<_> = (0..32).map(channel_slot).collect();
+        assert_eq!(slots[8], (0, 8));
+        assert_eq!(slots[9], (0, 10));
+        assert_eq!(slots[14], (0, 15));
+        assert_eq!(slots[15], (1, 0));
+        assert_eq!(slots[31], (2, 1));
+        assert!(!slots.contains(&(0, 9)) && !slots.contains(&(1, 9)));
+        assert_eq!(channel_slot(MAX_CHANNELS - 1), (127, 15));
+    }
+
+    #[test]
+    fn test_more_than_sixteen_channels() {
+        let channels: String = (0..20)
+            .map(|i| format!("Channel part{i}:\n return bar()\n"))
+            .collect();
+        let midigen = midigen_for(&format!(
+            "Pattern bar():\n return [1:16] Note(C)\n\
+             Section Tutti:\n {channels}\n\
+             Song Symphony:\n return Tutti()\n"
+        ));
+
+        let tracks = midigen.song_tracks(&midigen.songs["Symphony"]).unwrap();
+        assert_eq!(tracks.len(), 21);
+        assert!(
+            tracks[1..16]
+                .iter()
+                .all(|track| !matches!(track[1].kind, TrackEventKind::Meta(_)))
+        );
+        let last = &tracks[20];
+        assert!(matches!(
+            last[1].kind,
+            TrackEventKind::Meta(MetaMessage::MidiPort(port)) if port.as_int() == 1
+        ));
+        assert!(matches!(
+            last[2].kind,
+            TrackEventKind::Midi { channel, .. } if channel.as_int() == 4
+        ));
+    }
+
+    #[test]
+    fn test_pitches_play_single_notes() {
+        let midigen = midigen_for(
+            r#"
+            Pattern bass():
+                return [1:4] Pitch(E2) + [5:8] Pitch(Bb2) + [9:16] Note(C)
+
+            Section Verse:
+                Channel bass:
+                    return bass()
+
+            Song HotlineBling:
+                return Verse()
+            "#,
+        );
+
+        let tracks = midigen.song_tracks(&midigen.songs["HotlineBling"]).unwrap();
+        assert_eq!(
+            note_ticks(&tracks[1]),
+            vec![
+                (0, 40),
+                (480, -40),
+                (480, 46),
+                (960, -46),
+                (960, 60),
+                (960, 64),
+                (960, 67),
+                (1920, -60),
+                (1920, -64),
+                (1920, -67),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chords_are_voiced() {
+        let midigen = midigen_for(
+            r#"
+            Pattern comp():
+                return [1:8] Note(Am, octave=3, inversion=1) + [9:16] Note(C/E, voicing=open)
+
+            Section Verse:
+                Channel pad:
+                    return comp()
+
+            Song HotlineBling:
+                return Verse()
+            "#,
+        );
+
+        let tracks = midigen.song_tracks(&midigen.songs["HotlineBling"]).unwrap();
+        let onsets: Vec<_> = note_ticks(&tracks[1])
+            .into_iter()
+            .filter(|(_, key)| *key > 0)
+            .collect();
+        assert_eq!(
+            onsets,
+            vec![
+                (0, 60),
+                (0, 64),
+                (0, 69),
+                (960, 52),
+                (960, 60),
+                (960, 67),
+                (960, 76),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_smooth_voice_leading() {
+        let midigen = midigen_for(
+            r#"
+            Pattern changes():
+                return [1:4] Note(C) + [5:8] Note(Am) + [9:12] Note(F) + [13:16] Note(G)
+
+            Pattern fixed():
+                voice_leading: none
+                return [1:4] Note(C) + [5:8] Note(F)
+
+            Pattern explicit():
+                return [1:4] Note(C) + [5:8] Note(F, inversion=1)
+
+            Section Verse:
+                Channel pad:
+                    voice_leading: smooth
+                    return changes() + fixed() + explicit()
+                Channel keys:
+                    return changes()
+
+            Song HotlineBling:
+                return Verse()
+            "#,
+        );
+
+        let tracks = midigen.song_tracks(&midigen.songs["HotlineBling"]).unwrap();
+        let chords = |track: &[TrackEvent]| -> Vec<Vec<i16>> {
+            let mut chords: Vec<(u32, Vec<i16>)> = vec![];
+            for (tick, key) in note_ticks(track).into_iter().filter(|(_, key)| *key > 0) {
+                match chords.last_mut() {
+                    Some((last, notes)) if *last == tick => notes.push(key),
+                    _ => chords.push((tick, vec![key])),
+                }
+            }
+            chords.into_iter().map(|(_, notes)| notes).collect()
+        };
+        assert_eq!(
+            chords(&tracks[1]),
+            vec![
+                vec![60, 64, 67],
+                vec![60, 64, 69],
+                vec![60, 65, 69],
+                vec![59, 62, 67],
+                // `fixed()` turns voice leading off.
+                vec![60, 64, 67],
+                vec![65, 69, 72],
+                // The first chord of `explicit()` is led from F, the second
+                // sets its own inversion.
+                vec![64, 67, 72],
+                vec![69, 72, 77],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_velocities_follow_dynamics() {
+        let midigen = midigen_for(
+            r#"
+            Pattern loud():
+                dynamics: ff
+                return [1:4] Pitch(C4) + [5:8] Pitch(D4, vel=20)
+
+            Pattern plain():
+                return [1:4] Pitch(E4) + [5:8] Note(C, vel=90)
+
+            Pattern swell():
+                return [1:16] Crescendo(p, f) + [1:4] Pitch(C4) + [5:8] Pitch(D4) + [9:12] Pitch(E4) + [13:16] Pitch(F4)
+
+            Pattern fade():
+                return [1:8] Diminuendo(pp) + [1:2] Pitch(C4) + [3:4] Pitch(D4, vel=70) + [5:6] Pitch(E4) + [9:12] Pitch(F4)
+
+            Section Verse:
+                dynamics: p
+                Channel lead:
+                    return loud() + plain() + swell() + fade()
+
+            Song HotlineBling:
+                return Verse()
+            "#,
+        );
+
+        let tracks = midigen.song_tracks(&midigen.songs["HotlineBling"]).unwrap();
+        let velocities: Vec<u8> = tracks[1]
+            .iter()
+            .filter_map(|event| match event.kind {
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { vel, .. },
+                    ..
+                } => Some(vel.as_int()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            velocities,
+            vec![
+                // `loud()` overrides the section's dynamics.
+                112, 20, //
+                49, 90, 90, 90, //
+                49, 65, 80, 96, //
+                // `fade()` falls from the section's p, skipping the note
+                // with its own velocity, and ends with the hairpin.
+                49, 70, 33, 49,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rests_only_take_up_time() {
+        let midigen = midigen_for(
+            r#"
+            Pattern hit():
+                return [1:4] Pitch(C4) + [5:16] Rest()
+
+            Pattern pause():
+                return [1] Wait(2 bars)
+
+            Pattern again():
+                return [1:4] Pitch(D4) + [5:12] Wait()
+
+            Section Verse:
+                time_signature: 3/4
+                Channel lead:
+                    return hit() + pause() + again()
+
+            Song HotlineBling:
+                return Verse()
+            "#,
+        );
+
+        let tracks = midigen.song_tracks(&midigen.songs["HotlineBling"]).unwrap();
+        // Two bars of 3/4 are 2880 ticks.
+        assert_eq!(
+            note_ticks(&tracks[1]),
+            vec![(0, 60), (480, -60), (4800, 62), (5280, -62)]
+        );
+        let end: u32 = tracks[1].iter().map(|event| event.delta.as_int()).sum();
+        assert_eq!(end, 5280);
+    }
+
+    #[test]
+    fn test_pattern_arguments_are_substituted() {
+        let midigen = midigen_for(
+            r#"
+            Pattern groove(root, note, vel):
+                return [1:4] Note(root, vel=vel) + [5:8] Pitch(note)
+
+            Section Verse:
+                Channel lead:
+                    return groove(Am, E4, 90) + groove(C, G4, 40)
+
+            Song HotlineBling:
+                return Verse()
+            "#,
+        );
+
+        let tracks = midigen.song_tracks(&midigen.songs["HotlineBling"]).unwrap();
+        let onsets: Vec<(u32, u8, u8)> = {
+            let mut tick = 0;
+            let mut onsets = vec![];
+            for event in &tracks[1] {
+                tick += event.delta.as_int();
+                if let TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { key, vel },
+                    ..
+                } = event.kind
+                {
+                    onsets.push((tick, key.as_int(), vel.as_int()));
+                }
+            }
+            onsets
+        };
+        assert_eq!(
+            onsets,
+            vec![
+                (0, 69, 90),
+                (0, 72, 90),
+                (0, 76, 90),
+                (480, 64, 100),
+                (960, 60, 40),
+                (960, 64, 40),
+                (960, 67, 40),
+                (1440, 67, 100),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_section_arguments_are_forwarded() {
+        let midigen = midigen_for(
+            r#"
+            Pattern groove(root, vel):
+                return [1:4] Note(root, vel=vel)
+
+            Section Verse(key):
+                Channel lead:
+                    return groove(key, 50) + groove(C, 60)
+
+            Song HotlineBling:
+                return Verse(Am) + Verse(Dm)
+            "#,
+        );
+
+        let tracks = midigen.song_tracks(&midigen.songs["HotlineBling"]).unwrap();
+        let chords: Vec<(u32, i16)> = note_ticks(&tracks[1])
+            .into_iter()
+            .filter(|(_, key)| *key > 0)
+            .collect();
+        assert_eq!(
+            chords,
+            vec![
+                (0, 69),
+                (0, 72),
+                (0, 76),
+                (480, 60),
+                (480, 64),
+                (480, 67),
+                (960, 62),
+                (960, 65),
+                (960, 69),
+                (1440, 60),
+                (1440, 64),
+                (1440, 67),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repeats_play_back_to_back() {
+        let midigen = midigen_for(
+            r#"
+            Pattern hit():
+                return [1:4] Pitch(C4)
+
+            Pattern fill():
+                return [1:2] Pitch(D4)
+
+            Section Verse:
+                Channel lead:
+                    return repeat 2 { hit() + fill() * 2 }
+
+            Song HotlineBling:
+                return Verse() * 2
+            "#,
+        );
+
+        let tracks = midigen.song_tracks(&midigen.songs["HotlineBling"]).unwrap();
+        let onsets: Vec<(u32, i16)> = note_ticks(&tracks[1])
+            .into_iter()
+            .filter(|(_, key)| *key > 0)
+            .collect();
+        // Each pass of the block is 480 + 2 * 240 ticks long, and the
+        // section plays the block twice.
+        let pass = [(0, 60), (480, 62), (720, 62)];
+        let expected: Vec<(u32, i16)> = [0, 960, 1920, 2880]
+            .iter()
+            .flat_map(|offset| pass.iter().map(move |(tick, key)| (tick + offset, *key)))
+            .collect();
+        assert_eq!(onsets, expected);
+    }
+
+    #[test]
+    fn test_transpositions_add_up() {
+        let midigen = midigen_for(
+            r#"
+            Pattern riff():
+                return [1:4] Note(C) + [5:8] Pitch(C4)
+
+            Section Verse:
+                Channel lead:
+                    return riff() ^ +2 + riff().transpose(-12)
+
+            Song HotlineBling:
+                return Verse() + Verse().transpose(1)
+            "#,
+        );
+
+        let tracks = midigen.song_tracks(&midigen.songs["HotlineBling"]).unwrap();
+        let onsets: Vec<(u32, i16)> = note_ticks(&tracks[1])
+            .into_iter()
+            .filter(|(_, key)| *key > 0)
+            .collect();
+        assert_eq!(
+            onsets,
+            vec![
+                (0, 62),
+                (0, 66),
+                (0, 69),
+                (480, 62),
+                (960, 48),
+                (960, 52),
+                (960, 55),
+                (1440, 48),
+                (1920, 63),
+                (1920, 67),
+                (1920, 70),
+                (2400, 63),
+                (2880, 49),
+                (2880, 53),
+                (2880, 56),
+                (3360, 49),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transforms_reshape_patterns() {
+        let midigen = midigen_for(
+            r#"
+            Pattern motif():
+                return [1:2] Pitch(C4) + [3:4] Pitch(E4)
+
+            Section Verse:
+                Channel lead:
+                    return motif().augment(2).transpose(-12) + motif().reverse()
+
+            Song HotlineBling:
+                return Verse()
+            "#,
+        );
+
+        let tracks = midigen.song_tracks(&midigen.songs["HotlineBling"]).unwrap();
+        let onsets: Vec<(u32, i16)> = note_ticks(&tracks[1])
+            .into_iter()
+            .filter(|(_, key)| *key > 0)
+            .collect();
+        // The augmented motif lasts 8 steps, twice its written length.
+        assert_eq!(onsets, vec![(0, 48), (480, 52), (960, 64), (1200, 60)]);
+    }
+
+    #[test]
+    fn test_songs_too_long_for_a_midi_file() {
+        let midigen = midigen_for(
+            r#"
+            Pattern groove():
+                return [1:255] Note(Am)
+
+            Section Verse:
+                Channel bass:
+                    return groove() * 3000000
+
+            Song HotlineBling:
+                return Verse()
+            "#,
+        );
+
+        let error = midigen.render_song("HotlineBling").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the song lasts longer than a MIDI file can hold"
+        );
+    }
+
+    #[test]
+    fn test_songs_that_cannot_be_rendered_leave_no_file() {
+        let midigen = midigen_for(
+            r#"
+            Pattern groove():
+                return [1:255] Note(Am)
+
+            Section Verse:
+                Channel bass:
+                    return groove() * 3000000
+
+            Song HotlineBling:
+                return Verse()
+            "#,
+        );
+        let dir = std::env::temp_dir().join(format!("cricket-unrendered-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        assert!(midigen.generate_in(&dir).is_err());
+        assert!(!dir.join("HotlineBling.mid").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_unplayable_patterns_fail_to_render() {
+        let render = |body: &str| {
+            let midigen = midigen_for(&format!(
+                r#"
+                Pattern motif():
+                    return [1:4] Pitch(C4) + [5:8] Note(Am)
+
+                Pattern broken():
+                    return [1:4] Note(Ax7)
+
+                Pattern high():
+                    return [1:4] Note(Am, octave=9)
+
+                Section Verse:
+                    Channel lead:
+                        return {body}
+
+                Song HotlineBling:
+                    return Verse()
+                "#
+            ));
+            midigen.render_song("HotlineBling").unwrap_err().to_string()
+        };
+
+        assert_eq!(
+            render("motif().invert(around=C4)"),
+            "pattern \"motif\" cannot be transformed: `invert` only mirrors single pitches, but the pattern plays chord \"Am\""
+        );
+        assert_eq!(
+            render("broken()"),
+            "pattern \"broken\" plays \"Ax7\", which is not a valid chord: \"x7\" is not a known chord quality"
+        );
+        assert_eq!(
+            render("high()"),
+            "pattern \"high\" cannot voice \"Am\": the chord reaches MIDI note 129, outside of the MIDI range 0 to 127"
+        );
+    }
+
+    #[test]
+    fn test_program_changes_only_when_the_instrument_does() {
+        let midigen = midigen_for(
+            r#"
+            Instrument guitar:
+                type: AcousticGuitarNylon
+                midi_path: guitar
+
+            Instrument piano:
+                type: AcousticGrandPiano
+                midi_path: piano
+
+            Pattern groove():
+                return [1:4] Note(Am)
+
+            Section Verse:
+                Channel lead uses guitar:
+                    return groove()
+
+            Section Chorus:
+                Channel lead uses piano:
+                    return groove()
+
+            Song HotlineBling:
+                return Verse() * 2 + Chorus() + Verse()
+            "#,
+        );
+
+        let tracks = midigen.song_tracks(&midigen.songs["HotlineBling"]).unwrap();
+        let mut tick = 0;
+        let mut programs = vec![];
+        for event in &tracks[1] {
+            tick += event.delta.as_int();
+            if let TrackEventKind::Midi {
+                message: MidiMessage::ProgramChange { program },
+                ..
+            } = event.kind
+            {
+                programs.push((tick, program.as_int()));
+            }
+        }
+        assert_eq!(programs, vec![(0, 24), (960, 0), (1440, 24)]);
+    }
+
+    #[test]
+    fn test_repeats_that_take_no_time_play_once() {
+        let midigen = midigen_for(
+            r#"
+            Pattern e():
+                return
+
+            Pattern groove():
+                return [1:4] Note(Am)
+
+            Section Empty:
+
+            Section Verse:
+                Channel bass:
+                    return e() * 4000000000 + groove()
+
+            Song HotlineBling:
+                return Empty() * 4000000000 + Verse()
+            "#,
+        );
+
+        let tracks = midigen.song_tracks(&midigen.songs["HotlineBling"]).unwrap();
+        let onsets: Vec<(u32, i16)> = note_ticks(&tracks[1])
+            .into_iter()
+            .filter(|(_, key)| *key > 0)
+            .collect();
+        assert_eq!(onsets, vec![(0, 69), (0, 72), (0, 76)]);
+    }
+
+    #[test]
+    fn test_invalid_tempos_and_meters_fail_to_render() {
+        let render = |header: &str, events: &str| {
+            let midigen = midigen_for(&format!(
+                r#"
+                Pattern groove():
+                    return [1:4] Note(Am){events}
+
+                Section Verse:
+                    {header}
+                    Channel bass:
+                        return groove()
+
+                Song HotlineBling:
+                    return Verse()
+                "#
+            ));
+            midigen.render_song("HotlineBling").unwrap_err().to_string()
+        };
+
+        let slow = "tempo 0 is out of range, expected 10 to 1000 BPM";
+        assert_eq!(render("tempo: 0", ""), slow);
+        assert_eq!(render("", " + [2:2] Tempo(0)"), slow);
+        assert_eq!(
+            render("time_signature: 3/0", ""),
+            "time signature 3/0 is invalid"
+        );
+        assert_eq!(
+            render("time_signature: 3/3", ""),
+            "time signature 3/3 is invalid"
+        );
+    }
 }
@@ -1,13 +1,111 @@
+use std::str::FromStr;
+
+use thiserror::Error;
+
 use crate::ast::*;
-use crate::lexer::Token;
+use crate::lexer::{Span, Token};
+
+/// A syntax error found while parsing. The parser keeps going after one of
+/// these, so a single run can report several of them.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ParseError {
+    #[error("expected {}, found {}", expected_list(.expected), found_token(.found, .text))]
+    UnexpectedToken {
+        expected: Vec<Token>,
+        found: Token,
+        text: String,
+        span: Span,
+    },
+    #[error("expected {}, found end of input", expected_list(.expected))]
+    UnexpectedEof { expected: Vec<Token>, span: Span },
+    #[error(
+        "unknown pattern event {name:?}, expected `Note`, `Pitch`, `Wait`, `Rest`, `Tempo`, `Crescendo` or `Diminuendo`"
+    )]
+    UnknownEvent { name: String, span: Span },
+    #[error(
+        "expected the last step after `:`, only a rest of whole bars such as `[17] Wait(4 bars)` leaves it out"
+    )]
+    MissingLastStep { span: Span },
+    #[error("a rest of whole bars gives only its first step, as in `[17] Wait(4 bars)`")]
+    BarRestWithLastStep { span: Span },
+    #[error("unknown `{event}` option {name:?}, expected {}", event_options(.event))]
+    UnknownOption {
+        event: String,
+        name: String,
+        span: Span,
+    },
+    #[error("{value:?} is not a valid value for `{name}`")]
+    InvalidOptionValue {
+        name: String,
+        value: String,
+        span: Span,
+    },
+    #[error("{text:?} is not a valid number")]
+    InvalidNumber { text: String, span: Span },
+    #[error(
+        "unknown transform {name:?}, expected `transpose`, `reverse`, `retrograde`, `augment`, `diminish`, `invert` or `rotate`"
+    )]
+    UnknownTransform { name: String, span: Span },
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::UnexpectedToken { span, .. }
+            | ParseError::UnexpectedEof { span, .. }
+            | ParseError::UnknownEvent { span, .. }
+            | ParseError::MissingLastStep { span }
+            | ParseError::BarRestWithLastStep { span }
+            | ParseError::UnknownOption { span, .. }
+            | ParseError::InvalidOptionValue { span, .. }
+            | ParseError::InvalidNumber { span, .. }
+            | ParseError::UnknownTransform { span, .. } => *span,
+        }
+    }
+}
+
+fn event_options(event: &str) -> &'static str {
+    match event {
+        "Note" => "`octave`, `inversion`, `voicing` or `vel`",
+        "invert" => "`around`",
+        _ => "`vel`",
+    }
+}
+
+/// Describes the token that was found. Names and numbers also show their
+/// text, every other token already reads as the text it stands for.
+fn found_token(found: &Token, text: &str) -> String {
+    match found {
+        Token::Identifier | Token::Number => format!("{found} {text:?}"),
+        _ => found.to_string(),
+    }
+}
+
+fn expected_list(expected: &[Token]) -> String {
+    match expected {
+        [] => "nothing".to_string(),
+        [single] => single.to_string(),
+        [init @ .., last] => {
+            let init: Vec<String> = init.iter().map(|t| t.to_string()).collect();
+            format!("one of {} or {}", init.join(", "), last)
+        }
+    }
+}
+
+const TOP_LEVEL_TOKENS: [Token; 4] = [
+    Token::Instrument,
+    Token::Pattern,
+    Token::Section,
+    Token::Song,
+];
 
 pub struct Parser {
-    tokens: Vec<(Token, String)>,
+    tokens: Vec<(Token, String, Span)>,
     pos: usize,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<(Token, String)>) -> Self {
+    pub fn new(tokens: Vec<(Token, String, Span)>) -> Self {
         Parser { tokens, pos: 0 }
     }
 
@@ -15,121 +113,395 @@ impl Parser {
         self.tokens.get(self.pos).map(|t| &t.0)
     }
 
-    fn advance(&mut self) -> Option<(Token, String)> {
+    fn advance(&mut self) -> Option<(Token, String, Span)> {
         let tok = self.tokens.get(self.pos).cloned();
         self.pos += 1;
         tok
     }
 
-    fn expect(&mut self, expected: Token, location: String) -> String {
-        match self.advance() {
-            Some((tok, val)) if tok == expected => val,
-            Some((tok, _)) => panic!("Expected {:?}, found {:?} at {:?}", expected, tok, location),
-            None => panic!("Expected {:?}, found end of input", expected),
+    /// Span used for errors reported at the end of the input.
+    fn eof_span(&self) -> Span {
+        self.tokens.last().map_or(Span::default(), |t| Span {
+            start: t.2.end,
+            column: t.2.column + t.1.chars().count(),
+            ..t.2
+        })
+    }
+
+    /// Span from `start` to the end of the last consumed token.
+    fn span_from(&self, start: Span) -> Span {
+        match self.pos.checked_sub(1).and_then(|i| self.tokens.get(i)) {
+            Some((_, _, end)) => start.to(*end),
+            None => start,
+        }
+    }
+
+    fn unexpected(&self, expected: Vec<Token>) -> ParseError {
+        match self.tokens.get(self.pos) {
+            Some((found, text, span)) => ParseError::UnexpectedToken {
+                expected,
+                found: found.clone(),
+                text: text.clone(),
+                span: *span,
+            },
+            None => ParseError::UnexpectedEof {
+                expected,
+                span: self.eof_span(),
+            },
+        }
+    }
+
+    /// Consumes the next token if it is `expected`. On a mismatch the token is
+    /// left in place so error recovery can look at it.
+    fn expect(&mut self, expected: Token) -> Result<(String, Span), ParseError> {
+        match self.tokens.get(self.pos) {
+            Some((tok, _, _)) if *tok == expected => {
+                let (_, val, span) = self.advance().unwrap();
+                Ok((val, span))
+            }
+            _ => Err(self.unexpected(vec![expected])),
+        }
+    }
+
+    /// Like [`Self::expect`], but accepts any of the `expected` tokens.
+    fn expect_any(&mut self, expected: &[Token]) -> Result<(String, Span), ParseError> {
+        match self.peek() {
+            Some(tok) if expected.contains(tok) => {
+                let (_, val, span) = self.advance().unwrap();
+                Ok((val, span))
+            }
+            _ => Err(self.unexpected(expected.to_vec())),
         }
     }
 
-    pub fn parse(&mut self) -> Vec<TopLevel> {
+    fn expect_number<T: FromStr>(&mut self) -> Result<T, ParseError> {
+        let (text, span) = self.expect(Token::Number)?;
+        text.parse::<T>()
+            .map_err(|_| ParseError::InvalidNumber { text, span })
+    }
+
+    /// Parses a `tempo: <bpm>` property.
+    fn parse_tempo(&mut self) -> Result<(u16, Span), ParseError> {
+        let (_, start) = self.expect(Token::Tempo)?;
+        self.expect(Token::Colon)?;
+        let bpm = self.expect_number()?;
+        Ok((bpm, self.span_from(start)))
+    }
+
+    /// Parses a `time_signature: <numerator>/<denominator>` property.
+    fn parse_time_signature(&mut self) -> Result<(TimeSignature, Span), ParseError> {
+        let (_, start) = self.expect(Token::TimeSignature)?;
+        self.expect(Token::Colon)?;
+        let numerator = self.expect_number()?;
+        self.expect(Token::Slash)?;
+        let denominator = self.expect_number()?;
+        Ok((
+            TimeSignature {
+                numerator,
+                denominator,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    /// Parses a `voice_leading: none` or `voice_leading: smooth` property,
+    /// the latter with an optional `(<lowest>, <highest>)` pitch range.
+    fn parse_voice_leading(&mut self) -> Result<(VoiceLeading, Span), ParseError> {
+        let (_, start) = self.expect(Token::VoiceLeading)?;
+        self.expect(Token::Colon)?;
+        let (name, span) = self.expect(Token::Identifier)?;
+        let voice_leading = match name.as_str() {
+            "none" => VoiceLeading::None,
+            "smooth" => {
+                let mut range = None;
+                if let Some(Token::LParen) = self.peek() {
+                    self.advance();
+                    let (low, _) = self.parse_note_symbol()?;
+                    self.expect(Token::Comma)?;
+                    let (high, _) = self.parse_note_symbol()?;
+                    self.expect(Token::RParen)?;
+                    range = Some((low, high));
+                }
+                VoiceLeading::Smooth { range }
+            }
+            _ => {
+                return Err(ParseError::InvalidOptionValue {
+                    name: "voice_leading".to_string(),
+                    value: name,
+                    span,
+                });
+            }
+        };
+        Ok((voice_leading, self.span_from(start)))
+    }
+
+    /// Reads a dynamic marking such as `mf`, reporting any other word as an
+    /// invalid value for `name`.
+    fn parse_dynamic(&mut self, name: &str) -> Result<Dynamic, ParseError> {
+        let (value, span) = self.expect(Token::Identifier)?;
+        Dynamic::from_name(&value).ok_or_else(|| ParseError::InvalidOptionValue {
+            name: name.to_string(),
+            value,
+            span,
+        })
+    }
+
+    /// Parses a `dynamics: <marking>` property.
+    fn parse_dynamics(&mut self) -> Result<(Dynamic, Span), ParseError> {
+        let (_, start) = self.expect(Token::Dynamics)?;
+        self.expect(Token::Colon)?;
+        let dynamic = self.parse_dynamic("dynamics")?;
+        Ok((dynamic, self.span_from(start)))
+    }
+
+    /// Skips tokens until the start of the next top-level item.
+    fn synchronize(&mut self) {
+        while let Some(tok) = self.peek() {
+            if TOP_LEVEL_TOKENS.contains(tok) {
+                break;
+            }
+            self.pos += 1;
+        }
+    }
+
+    pub fn parse(&mut self) -> Result<Vec<TopLevel>, Vec<ParseError>> {
         let mut items = vec![];
+        let mut errors = vec![];
         while let Some(token) = self.peek() {
             let item = match token {
                 Token::Instrument => self.parse_instrument(),
                 Token::Pattern => self.parse_pattern(),
                 Token::Section => self.parse_section(),
                 Token::Song => self.parse_song(),
-                _ => panic!("Unexpected token: {:?}", token),
+                _ => {
+                    let err = self.unexpected(TOP_LEVEL_TOKENS.to_vec());
+                    self.pos += 1;
+                    Err(err)
+                }
             };
-            items.push(item);
+            match item {
+                Ok(item) => items.push(item),
+                Err(err) => {
+                    errors.push(err);
+                    self.synchronize();
+                }
+            }
         }
-        items
-    }
 
-    pub fn is_identifier_chord(&mut self, identifier: &String) -> bool {
-        if identifier.is_empty() {
-            return false;
+        if errors.is_empty() {
+            Ok(items)
+        } else {
+            Err(errors)
         }
+    }
 
-        if !identifier
-            .chars()
-            .next()
-            .map_or(false, |c| "ABCDEFG".contains(c))
+    /// Reads a chord symbol such as `C#m7` or `C/E`, or a pitch such as
+    /// `C#4` or `C-1`, which the lexer splits into identifiers, numbers, `#`,
+    /// `/` and `-`. The parts have to touch.
+    fn parse_note_symbol(&mut self) -> Result<(String, Span), ParseError> {
+        let (mut symbol, mut span) = self.expect(Token::Identifier)?;
+        while let Some((token, text, next)) = self.tokens.get(self.pos)
+            && matches!(
+                token,
+                Token::Identifier | Token::Number | Token::Hash | Token::Slash | Token::Minus
+            )
+            && next.start == span.end
         {
-            return false;
+            symbol.push_str(text);
+            span = span.to(*next);
+            self.pos += 1;
         }
+        Ok((symbol, span))
+    }
 
-        let rest = &identifier[1..]; // Ignore the first character, which is the chord root (A-G)
-
-        let is_valid_chord = rest
-            .chars()
-            .all(|c| c.is_alphanumeric() || c == '#' || c == 'b' || c == 'm' || c == '7');
-
-        if is_valid_chord {
-            return true;
+    /// Parses the `, name=value` options after the chord symbol or pitch of
+    /// `event`. Only `Note` takes the voicing options.
+    fn parse_note_options(
+        &mut self,
+        event: &str,
+    ) -> Result<(Voicing, Option<Value<u8>>), ParseError> {
+        let mut voicing = Voicing::default();
+        let mut velocity = None;
+        while let Some(Token::Comma) = self.peek() {
+            self.advance();
+            let (name, span) = self.expect(Token::Identifier)?;
+            self.expect(Token::Equals)?;
+            match name.as_str() {
+                "vel" => velocity = Some(self.parse_value()?),
+                "octave" if event == "Note" => voicing.octave = Some(self.expect_number()?),
+                "inversion" if event == "Note" => voicing.inversion = Some(self.expect_number()?),
+                "voicing" if event == "Note" => {
+                    let (value, span) = self.expect(Token::Identifier)?;
+                    voicing.spread = match value.as_str() {
+                        "close" => Spread::Close,
+                        "open" => Spread::Open,
+                        "drop2" => Spread::Drop2,
+                        _ => return Err(ParseError::InvalidOptionValue { name, value, span }),
+                    };
+                }
+                _ => {
+                    return Err(ParseError::UnknownOption {
+                        event: event.to_string(),
+                        name,
+                        span,
+                    });
+                }
+            }
         }
+        Ok((voicing, velocity))
+    }
 
-        false
+    /// Reads a number, or the name of the parameter it comes from.
+    fn parse_value<T: FromStr>(&mut self) -> Result<Value<T>, ParseError> {
+        match self.peek() {
+            Some(Token::Identifier) => {
+                let (name, _) = self.expect(Token::Identifier)?;
+                Ok(Value::Parameter(name))
+            }
+            _ => Ok(Value::Literal(self.expect_number()?)),
+        }
     }
 
-    fn parse_instrument(&mut self) -> TopLevel {
-        self.expect(Token::Instrument, stringify!("instrument").to_string());
-        let name = self.expect(Token::Identifier, stringify!("instrument").to_string());
-        self.expect(Token::Colon, stringify!("instrument").to_string());
-        self.expect(Token::Type, stringify!("instrument").to_string());
-        self.expect(Token::Colon, stringify!("instrument").to_string());
-        let type_ = self.expect(Token::Identifier, stringify!("instrument").to_string());
-        self.expect(Token::MidiPath, stringify!("instrument").to_string());
-        self.expect(Token::Colon, stringify!("instrument").to_string());
-        let midi_path = self.expect(Token::Identifier, stringify!("instrument").to_string());
-        TopLevel::Instrument(Instrument {
+    fn parse_instrument(&mut self) -> Result<TopLevel, ParseError> {
+        let (_, start) = self.expect(Token::Instrument)?;
+        let (name, _) = self.expect(Token::Identifier)?;
+        self.expect(Token::Colon)?;
+        self.expect(Token::Type)?;
+        self.expect(Token::Colon)?;
+        let (type_, _) = self.expect_any(&[Token::Identifier, Token::Number])?;
+        self.expect(Token::MidiPath)?;
+        self.expect(Token::Colon)?;
+        let (midi_path, _) = self.expect(Token::Identifier)?;
+        Ok(TopLevel::Instrument(Instrument {
             name,
             type_,
             midi_path,
-        })
+            span: self.span_from(start),
+        }))
     }
 
-    fn parse_pattern(&mut self) -> TopLevel {
-        self.expect(Token::Pattern, stringify!("Pattern").to_string());
-        let name = self.expect(Token::Identifier, stringify!("Pattern").to_string());
-        self.expect(Token::LParen, stringify!("Pattern").to_string());
-        self.expect(Token::RParen, stringify!("Pattern").to_string());
-        self.expect(Token::Colon, stringify!("Pattern").to_string());
-        self.expect(Token::Return, stringify!("Pattern").to_string());
+    fn parse_pattern(&mut self) -> Result<TopLevel, ParseError> {
+        let (_, start) = self.expect(Token::Pattern)?;
+        let (name, _) = self.expect(Token::Identifier)?;
+        let params = self.parse_parameters()?;
+        self.expect(Token::Colon)?;
+        let mut voice_leading = None;
+        let mut dynamics = None;
+        loop {
+            match self.peek() {
+                Some(Token::VoiceLeading) => voice_leading = Some(self.parse_voice_leading()?),
+                Some(Token::Dynamics) => dynamics = Some(self.parse_dynamics()?),
+                _ => break,
+            }
+        }
+        self.expect(Token::Return)?;
         let mut events = vec![];
         while let Some(Token::LBracket) = self.peek() {
-            self.expect(Token::LBracket, stringify!("Pattern").to_string());
-            let num = self
-                .expect(Token::Number, stringify!("Pattern").to_string())
-                .parse::<u8>()
-                .unwrap();
-            self.expect(Token::Colon, stringify!("Pattern").to_string());
-            let denom = self
-                .expect(Token::Number, stringify!("Pattern").to_string())
-                .parse::<u8>()
-                .unwrap();
-            self.expect(Token::RBracket, stringify!("Pattern").to_string());
-
-            match self.advance() {
-                Some((Token::Identifier, ident)) if ident == "Wait" => {
-                    self.expect(Token::LParen, stringify!("Pattern").to_string());
-                    self.expect(Token::RParen, stringify!("Pattern").to_string());
-                    events.push(PatternEvent::Wait {
-                        duration: (num, denom),
-                    });
+            let (_, event_start) = self.expect(Token::LBracket)?;
+            let first = self.expect_number()?;
+            let last = match self.peek() {
+                Some(Token::RBracket) => None,
+                _ => {
+                    self.expect(Token::Colon)?;
+                    Some(self.expect_number()?)
                 }
-                Some((Token::Identifier, ident)) if ident == "Note" => {
-                    self.expect(Token::LParen, stringify!("Pattern").to_string());
-                    match self.advance() {
-                        Some((Token::Identifier, chord)) if self.is_identifier_chord(&chord) => {
-                            events.push(PatternEvent::Note {
-                                chord,
-                                duration: (num, denom),
+            };
+            self.expect(Token::RBracket)?;
+            let steps = self.span_from(event_start);
+
+            let (ident, span) = self.expect(Token::Identifier)?;
+            let bar_rest = matches!(ident.as_str(), "Wait" | "Rest")
+                && matches!(
+                    self.tokens.get(self.pos..self.pos + 2),
+                    Some([(Token::LParen, ..), (Token::Number, ..)])
+                );
+            let duration = match (last, bar_rest) {
+                (Some(last), false) => (first, last),
+                (None, true) => (first, first),
+                (None, false) => return Err(ParseError::MissingLastStep { span: steps }),
+                (Some(_), true) => return Err(ParseError::BarRestWithLastStep { span: steps }),
+            };
+            match ident.as_str() {
+                "Wait" | "Rest" => {
+                    self.expect(Token::LParen)?;
+                    let mut bars = None;
+                    if bar_rest {
+                        bars = Some(self.expect_number()?);
+                        let (unit, span) = self.expect(Token::Identifier)?;
+                        if !matches!(unit.as_str(), "bar" | "bars") {
+                            return Err(ParseError::InvalidOptionValue {
+                                name: ident,
+                                value: unit,
+                                span,
                             });
-                            self.expect(Token::RParen, stringify!("Pattern").to_string());
                         }
-                        other => panic!("Note is not accepted as {:?}", other),
                     }
+                    self.expect(Token::RParen)?;
+                    events.push(PatternEvent::Wait {
+                        bars,
+                        duration,
+                        span: self.span_from(event_start),
+                    });
+                }
+                "Tempo" => {
+                    self.expect(Token::LParen)?;
+                    let bpm = self.expect_number()?;
+                    self.expect(Token::RParen)?;
+                    events.push(PatternEvent::Tempo {
+                        bpm,
+                        duration,
+                        span: self.span_from(event_start),
+                    });
+                }
+                "Note" => {
+                    self.expect(Token::LParen)?;
+                    let (chord, _) = self.parse_note_symbol()?;
+                    let (voicing, velocity) = self.parse_note_options("Note")?;
+                    self.expect(Token::RParen)?;
+                    events.push(PatternEvent::Note {
+                        chord,
+                        voicing,
+                        velocity,
+                        duration,
+                        span: self.span_from(event_start),
+                    });
                 }
-                other => panic!("Unexpected token in pattern event: {:?}", other),
+                "Pitch" => {
+                    self.expect(Token::LParen)?;
+                    let (pitch, _) = self.parse_note_symbol()?;
+                    let (_, velocity) = self.parse_note_options("Pitch")?;
+                    self.expect(Token::RParen)?;
+                    events.push(PatternEvent::Pitch {
+                        pitch,
+                        velocity,
+                        duration,
+                        span: self.span_from(event_start),
+                    });
+                }
+                "Crescendo" | "Diminuendo" => {
+                    let kind = if ident == "Crescendo" {
+                        HairpinKind::Crescendo
+                    } else {
+                        HairpinKind::Diminuendo
+                    };
+                    self.expect(Token::LParen)?;
+                    let mut from = None;
+                    let mut to = self.parse_dynamic(&ident)?;
+                    if let Some(Token::Comma) = self.peek() {
+                        self.advance();
+                        from = Some(to);
+                        to = self.parse_dynamic(&ident)?;
+                    }
+                    self.expect(Token::RParen)?;
+                    events.push(PatternEvent::Hairpin {
+                        kind,
+                        from,
+                        to,
+                        duration,
+                        span: self.span_from(event_start),
+                    });
+                }
+                _ => return Err(ParseError::UnknownEvent { name: ident, span }),
             }
 
             if let Some(Token::Plus) = self.peek() {
@@ -137,72 +509,287 @@ impl Parser {
             }
         }
 
-        TopLevel::Pattern(Pattern { name, events })
+        Ok(TopLevel::Pattern(Pattern {
+            name,
+            params,
+            voice_leading,
+            dynamics,
+            events,
+            span: self.span_from(start),
+        }))
     }
 
-    fn parse_section(&mut self) -> TopLevel {
-        self.expect(Token::Section, stringify!("Section").to_string());
-        let name = self.expect(Token::Identifier, stringify!("Section").to_string());
-        self.expect(Token::Colon, stringify!("Section").to_string());
+    fn parse_section(&mut self) -> Result<TopLevel, ParseError> {
+        let (_, start) = self.expect(Token::Section)?;
+        let (name, _) = self.expect(Token::Identifier)?;
+        let params = match self.peek() {
+            Some(Token::LParen) => self.parse_parameters()?,
+            _ => vec![],
+        };
+        self.expect(Token::Colon)?;
+        let mut tempo = None;
+        let mut time_signature = None;
+        let mut dynamics = None;
+        loop {
+            match self.peek() {
+                Some(Token::Tempo) => tempo = Some(self.parse_tempo()?),
+                Some(Token::TimeSignature) => time_signature = Some(self.parse_time_signature()?),
+                Some(Token::Dynamics) => dynamics = Some(self.parse_dynamics()?),
+                _ => break,
+            }
+        }
 
         let mut channels = vec![];
 
         while let Some(Token::Channel) = self.peek() {
-            self.expect(Token::Channel, stringify!("Section-channel").to_string());
-            let chan_name =
-                self.expect(Token::Identifier, stringify!("Section-channel").to_string());
-            self.expect(Token::Colon, stringify!("Section-channel").to_string());
-
-            let mut calls = vec![];
-
-            self.expect(Token::Return, stringify!("Section-channel").to_string());
+            let (_, chan_start) = self.expect(Token::Channel)?;
+            let (chan_name, _) = self.expect(Token::Identifier)?;
+            let instrument = match self.peek() {
+                Some(Token::Uses) => {
+                    self.advance();
+                    Some(self.expect(Token::Identifier)?)
+                }
+                _ => None,
+            };
+            self.expect(Token::Colon)?;
+            let voice_leading = match self.peek() {
+                Some(Token::VoiceLeading) => Some(self.parse_voice_leading()?),
+                _ => None,
+            };
 
-            calls.push(self.expect(Token::Identifier, stringify!("Section-channel").to_string()));
-            self.expect(Token::LParen, stringify!("Section-channel").to_string());
-            // We might have some parameters here
-            self.expect(Token::RParen, stringify!("Section-channel").to_string());
-
-            while let Some(Token::Plus) = self.peek() {
-                self.expect(Token::Plus, stringify!("Section-channel").to_string());
-                calls.push(
-                    self.expect(Token::Identifier, stringify!("Section-channel").to_string()),
-                );
-                self.expect(Token::LParen, stringify!("Section-channel").to_string());
-                // We might have some parameters here
-                self.expect(Token::RParen, stringify!("Section-channel").to_string());
-            }
+            self.expect(Token::Return)?;
+            let calls = self.parse_sequence(Self::parse_pattern_call)?;
 
             channels.push(Channel {
                 name: chan_name,
+                instrument,
+                voice_leading,
                 pattern_calls: calls,
+                span: self.span_from(chan_start),
             });
         }
 
-        TopLevel::Section(Section { name, channels })
+        Ok(TopLevel::Section(Section {
+            name,
+            params,
+            tempo,
+            time_signature,
+            dynamics,
+            channels,
+            span: self.span_from(start),
+        }))
     }
 
-    fn parse_song(&mut self) -> TopLevel {
-        self.expect(Token::Song, stringify!("song").to_string());
-        let name = self.expect(Token::Identifier, stringify!("song").to_string());
-        self.expect(Token::Colon, stringify!("song").to_string());
-        self.expect(Token::Return, stringify!("song").to_string());
+    fn parse_song(&mut self) -> Result<TopLevel, ParseError> {
+        let (_, start) = self.expect(Token::Song)?;
+        let (name, _) = self.expect(Token::Identifier)?;
+        self.expect(Token::Colon)?;
+        let mut tempo = None;
+        let mut time_signature = None;
+        loop {
+            match self.peek() {
+                Some(Token::Tempo) => tempo = Some(self.parse_tempo()?),
+                Some(Token::TimeSignature) => time_signature = Some(self.parse_time_signature()?),
+                _ => break,
+            }
+        }
+        self.expect(Token::Return)?;
+        let sections = self.parse_sequence(Self::parse_section_call)?;
+
+        Ok(TopLevel::Song(Song {
+            name,
+            tempo,
+            time_signature,
+            entry_sections: sections,
+            span: self.span_from(start),
+        }))
+    }
 
-        let mut sections = vec![];
-        sections.push(self.expect(Token::Identifier, stringify!("song").to_string()));
-        self.expect(Token::LParen, stringify!("song").to_string());
-        // We might have some parameters here
-        self.expect(Token::RParen, stringify!("song").to_string());
+    /// Parses a `+` separated list of calls read by `call`, each of which may
+    /// be repeated with `* <count>` or grouped in a `repeat <count> { ... }`
+    /// block.
+    fn parse_sequence<T>(
+        &mut self,
+        call: fn(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<Vec<Sequence<T>>, ParseError> {
+        let mut items = vec![self.parse_sequence_item(call)?];
         while let Some(Token::Plus) = self.peek() {
-            self.expect(Token::Plus, stringify!("song").to_string());
-            sections.push(self.expect(Token::Identifier, stringify!("song").to_string()));
-            self.expect(Token::LParen, stringify!("song").to_string());
-            // We might have some parameters here
-            self.expect(Token::RParen, stringify!("song").to_string());
+            self.advance();
+            items.push(self.parse_sequence_item(call)?);
         }
+        Ok(items)
+    }
 
-        TopLevel::Song(Song {
+    fn parse_sequence_item<T>(
+        &mut self,
+        call: fn(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<Sequence<T>, ParseError> {
+        let start = self
+            .tokens
+            .get(self.pos)
+            .map_or_else(|| self.eof_span(), |(_, _, span)| *span);
+        let mut item = match self.peek() {
+            Some(Token::Repeat) => {
+                self.advance();
+                let count = self.expect_number()?;
+                self.expect(Token::LBrace)?;
+                let body = self.parse_sequence(call)?;
+                self.expect(Token::RBrace)?;
+                Sequence::Repeat {
+                    count,
+                    body,
+                    span: self.span_from(start),
+                }
+            }
+            _ => Sequence::Call(call(self)?),
+        };
+        while let Some(Token::Star) = self.peek() {
+            self.advance();
+            let count = self.expect_number()?;
+            item = Sequence::Repeat {
+                count,
+                body: vec![item],
+                span: self.span_from(start),
+            };
+        }
+        Ok(item)
+    }
+
+    /// Parses the parenthesised, comma separated parameter names of a
+    /// pattern or section.
+    fn parse_parameters(&mut self) -> Result<Vec<(String, Span)>, ParseError> {
+        self.expect(Token::LParen)?;
+        let mut params = vec![];
+        if let Some(Token::Identifier) = self.peek() {
+            params.push(self.expect(Token::Identifier)?);
+            while let Some(Token::Comma) = self.peek() {
+                self.advance();
+                params.push(self.expect(Token::Identifier)?);
+            }
+        }
+        self.expect(Token::RParen)?;
+        Ok(params)
+    }
+
+    /// Parses a section call such as `Verse(Am)`.
+    fn parse_section_call(&mut self) -> Result<SectionCall, ParseError> {
+        let (name, start) = self.expect(Token::Identifier)?;
+        let args = self.parse_arguments()?;
+        let transforms = self.parse_transforms()?;
+        Ok(SectionCall {
             name,
-            entry_sections: sections,
+            args,
+            transforms,
+            span: self.span_from(start),
+        })
+    }
+
+    /// Parses a pattern call such as `groove(Am, 90)`.
+    fn parse_pattern_call(&mut self) -> Result<PatternCall, ParseError> {
+        let (name, start) = self.expect(Token::Identifier)?;
+        let args = self.parse_arguments()?;
+        let transforms = self.parse_transforms()?;
+        Ok(PatternCall {
+            name,
+            args,
+            transforms,
+            span: self.span_from(start),
         })
     }
+
+    /// Parses the transforms written after a call's arguments, such as
+    /// `.augment(2)`, `.transpose(5)` or its shorthand `^ +5`.
+    fn parse_transforms(&mut self) -> Result<Vec<(Transform, Span)>, ParseError> {
+        let mut transforms = vec![];
+        loop {
+            match self.peek() {
+                Some(Token::Caret) => {
+                    let (_, start) = self.expect(Token::Caret)?;
+                    let semitones = self.parse_signed()?;
+                    transforms.push((Transform::Transpose(semitones), self.span_from(start)));
+                }
+                Some(Token::Dot) => {
+                    let (_, start) = self.expect(Token::Dot)?;
+                    let (name, span) = self.expect(Token::Identifier)?;
+                    self.expect(Token::LParen)?;
+                    let transform = match name.as_str() {
+                        "transpose" => Transform::Transpose(self.parse_signed()?),
+                        "reverse" | "retrograde" => Transform::Reverse,
+                        "augment" => Transform::Augment(self.expect_number()?),
+                        "diminish" => Transform::Diminish(self.expect_number()?),
+                        "invert" => {
+                            let (option, span) = self.expect(Token::Identifier)?;
+                            if option != "around" {
+                                return Err(ParseError::UnknownOption {
+                                    event: name,
+                                    name: option,
+                                    span,
+                                });
+                            }
+                            self.expect(Token::Equals)?;
+                            let (around, _) = self.parse_note_symbol()?;
+                            Transform::Invert { around }
+                        }
+                        "rotate" => Transform::Rotate(self.parse_signed()?),
+                        _ => return Err(ParseError::UnknownTransform { name, span }),
+                    };
+                    self.expect(Token::RParen)?;
+                    transforms.push((transform, self.span_from(start)));
+                }
+                _ => return Ok(transforms),
+            }
+        }
+    }
+
+    /// Reads a whole number with an optional `+` or `-` sign.
+    fn parse_signed(&mut self) -> Result<i32, ParseError> {
+        let negative = match self.peek() {
+            Some(Token::Minus) => {
+                self.advance();
+                true
+            }
+            Some(Token::Plus) => {
+                self.advance();
+                false
+            }
+            _ => false,
+        };
+        let number: i32 = self.expect_number()?;
+        Ok(if negative { -number } else { number })
+    }
+
+    /// Parses the parenthesised, comma separated arguments of a call. Each
+    /// is a number or a symbol such as `Am`, `C#4` or a parameter name.
+    fn parse_arguments(&mut self) -> Result<Vec<(Argument, Span)>, ParseError> {
+        self.expect(Token::LParen)?;
+        let mut args = vec![];
+        if let Some(Token::RParen) = self.peek() {
+            self.advance();
+            return Ok(args);
+        }
+        loop {
+            let arg = match self.peek() {
+                Some(Token::Number) => {
+                    let (text, span) = self.expect(Token::Number)?;
+                    let number = text
+                        .parse()
+                        .map_err(|_| ParseError::InvalidNumber { text, span })?;
+                    (Argument::Number(number), span)
+                }
+                Some(Token::Identifier) => {
+                    let (symbol, span) = self.parse_note_symbol()?;
+                    (Argument::Symbol(symbol), span)
+                }
+                _ => return Err(self.unexpected(vec![Token::Identifier, Token::Number])),
+            };
+            args.push(arg);
+            if let Some(Token::Comma) = self.peek() {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        self.expect(Token::RParen)?;
+        Ok(args)
+    }
 }
@@ -1,60 +1,659 @@
-use core::panic;
-use std::{
-    collections::{HashMap, HashSet},
-    fmt::Result,
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::ast::{
+    Argument, Dynamic, HairpinKind, Instrument, Pattern, PatternCall, PatternEvent, Section,
+    SectionCall, Sequence, Song, Span, TimeSignature, TopLevel, Transform, Value, VoiceLeading,
 };
+use crate::chord::{self, ChordError, PitchError, VoicingError};
+use crate::gm;
+use crate::midigen::{self, MAX_CHANNELS, MAX_TEMPO, MIN_TEMPO};
+use crate::timing::Beats;
+use crate::transform::{self, TransformError};
+
+/// The kinds of top-level definitions, used to describe duplicates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionKind {
+    Instrument,
+    Pattern,
+    Section,
+    Song,
+}
+
+impl fmt::Display for DefinitionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            DefinitionKind::Instrument => "instrument",
+            DefinitionKind::Pattern => "pattern",
+            DefinitionKind::Section => "section",
+            DefinitionKind::Song => "song",
+        };
+        f.write_str(text)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum SemanticError {
+    #[error("{kind} {name:?} is defined more than once (first defined at {first})")]
+    DuplicateDefinition {
+        kind: DefinitionKind,
+        name: String,
+        span: Span,
+        first: Span,
+    },
+    #[error("song {song:?} uses section {section:?}, which is not defined")]
+    UndefinedSection {
+        song: String,
+        section: String,
+        span: Span,
+    },
+    #[error(
+        "channel {channel:?} in section {section:?} calls pattern {pattern:?}, which is not defined"
+    )]
+    UndefinedPattern {
+        section: String,
+        channel: String,
+        pattern: String,
+        span: Span,
+    },
+    #[error(
+        "channel {channel:?} in section {section:?} uses instrument {instrument:?}, which is not defined"
+    )]
+    UndefinedInstrument {
+        section: String,
+        channel: String,
+        instrument: String,
+        span: Span,
+    },
+    #[error(
+        "instrument {instrument:?} has type {type_:?}, which is neither a General MIDI program name nor a number from 0 to 127"
+    )]
+    UnknownInstrumentType {
+        instrument: String,
+        type_: String,
+        span: Span,
+    },
+    #[error("pattern {pattern:?} plays {chord:?}, which is not a valid chord: {reason}")]
+    InvalidChord {
+        pattern: String,
+        chord: String,
+        reason: ChordError,
+        span: Span,
+    },
+    #[error("pattern {pattern:?} cannot voice {chord:?}: {reason}")]
+    InvalidVoicing {
+        pattern: String,
+        chord: String,
+        reason: VoicingError,
+        span: Span,
+    },
+    #[error("pattern {pattern:?} plays {pitch:?}, which is not a valid pitch: {reason}")]
+    InvalidPitch {
+        pattern: String,
+        pitch: String,
+        reason: PitchError,
+        span: Span,
+    },
+    #[error(
+        "voice range {low} to {high} is invalid, it needs two pitches at least an octave apart, the lowest first"
+    )]
+    InvalidVoiceRange {
+        low: String,
+        high: String,
+        span: Span,
+    },
+    #[error(
+        "pattern {pattern:?} plays {chord:?}, which cannot be voiced within the voice leading range {low} to {high}"
+    )]
+    ChordOutsideVoiceRange {
+        pattern: String,
+        chord: String,
+        low: String,
+        high: String,
+        span: Span,
+    },
+    #[error(
+        "steps [{first}:{last}] are invalid, steps are counted from 1 and the last step cannot come before the first"
+    )]
+    InvalidSteps { first: u8, last: u8, span: Span },
+    #[error("{kind} {name:?} declares parameter {parameter:?} more than once")]
+    DuplicateParameter {
+        kind: DefinitionKind,
+        name: String,
+        parameter: String,
+        span: Span,
+    },
+    #[error("pattern {pattern:?} uses parameter {parameter:?}, which it does not declare")]
+    UndefinedParameter {
+        pattern: String,
+        parameter: String,
+        span: Span,
+    },
+    #[error("{kind} {name:?} takes {expected} argument(s), but is called with {found}")]
+    WrongArgumentCount {
+        kind: DefinitionKind,
+        name: String,
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    #[error(
+        "argument {argument:?} for parameter {parameter:?} of pattern {pattern:?} is not {expected}"
+    )]
+    InvalidArgument {
+        pattern: String,
+        parameter: String,
+        argument: String,
+        expected: &'static str,
+        span: Span,
+    },
+    #[error("a repeat has to play at least once")]
+    InvalidRepeat { span: Span },
+    #[error("a rest of whole bars has to last at least one bar")]
+    EmptyRest { span: Span },
+    #[error("velocity {velocity} is out of range, expected 1 to 127")]
+    InvalidVelocity { velocity: u8, span: Span },
+    #[error("{kind} from {from} to {to} does not go the right way")]
+    InvalidHairpin {
+        kind: HairpinKind,
+        from: Dynamic,
+        to: Dynamic,
+        span: Span,
+    },
+    #[error(
+        "transposing pattern {pattern:?} by {semitones} semitones takes {note:?} outside of the MIDI range 0 to 127"
+    )]
+    TransposedOutOfRange {
+        pattern: String,
+        semitones: i32,
+        note: String,
+        span: Span,
+    },
+    #[error("pattern {pattern:?} cannot be transformed: {reason}")]
+    InvalidTransform {
+        pattern: String,
+        reason: TransformError,
+        span: Span,
+    },
+    #[error("section {section:?} can only be transposed, `{transform}` applies to pattern calls")]
+    SectionTransform {
+        section: String,
+        transform: &'static str,
+        span: Span,
+    },
+    #[error("tempo {bpm} is out of range, expected {MIN_TEMPO} to {MAX_TEMPO} BPM")]
+    InvalidTempo { bpm: u16, span: Span },
+    #[error(
+        "time signature {time_signature} is invalid, the numerator must be at least 1 and the denominator a power of two"
+    )]
+    InvalidTimeSignature {
+        time_signature: TimeSignature,
+        span: Span,
+    },
+    #[error(
+        "section {section:?} defines channel {channel:?} more than once (first defined at {first})"
+    )]
+    DuplicateChannel {
+        section: String,
+        channel: String,
+        span: Span,
+        first: Span,
+    },
+    #[error(
+        "song {song:?} uses {count} differently named channels, but at most {MAX_CHANNELS} fit on the available MIDI ports"
+    )]
+    TooManyChannels {
+        song: String,
+        count: usize,
+        span: Span,
+    },
+    #[error("song {song:?} lasts longer than a MIDI file can hold")]
+    SongTooLong { song: String, span: Span },
+}
 
-use crate::ast::{Instrument, Pattern, Section, Song, TopLevel};
+impl SemanticError {
+    pub fn span(&self) -> Span {
+        match self {
+            SemanticError::DuplicateDefinition { span, .. }
+            | SemanticError::UndefinedSection { span, .. }
+            | SemanticError::UndefinedPattern { span, .. }
+            | SemanticError::UndefinedInstrument { span, .. }
+            | SemanticError::UnknownInstrumentType { span, .. }
+            | SemanticError::InvalidChord { span, .. }
+            | SemanticError::InvalidVoicing { span, .. }
+            | SemanticError::InvalidPitch { span, .. }
+            | SemanticError::InvalidVoiceRange { span, .. }
+            | SemanticError::ChordOutsideVoiceRange { span, .. }
+            | SemanticError::InvalidSteps { span, .. }
+            | SemanticError::DuplicateParameter { span, .. }
+            | SemanticError::UndefinedParameter { span, .. }
+            | SemanticError::WrongArgumentCount { span, .. }
+            | SemanticError::InvalidArgument { span, .. }
+            | SemanticError::InvalidRepeat { span }
+            | SemanticError::EmptyRest { span }
+            | SemanticError::InvalidVelocity { span, .. }
+            | SemanticError::InvalidHairpin { span, .. }
+            | SemanticError::TransposedOutOfRange { span, .. }
+            | SemanticError::InvalidTransform { span, .. }
+            | SemanticError::SectionTransform { span, .. }
+            | SemanticError::InvalidTempo { span, .. }
+            | SemanticError::InvalidTimeSignature { span, .. }
+            | SemanticError::DuplicateChannel { span, .. }
+            | SemanticError::TooManyChannels { span, .. }
+            | SemanticError::SongTooLong { span, .. } => *span,
+        }
+    }
+}
+
+/// Problems that do not stop compilation but are likely mistakes.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum SemanticWarning {
+    #[error("{kind} {name:?} is never used")]
+    Unused {
+        kind: DefinitionKind,
+        name: String,
+        span: Span,
+    },
+    #[error(
+        "pattern {pattern:?} is {} steps long, which is not a whole number of {time_signature} bars",
+        .length.scale(4, 1)
+    )]
+    PartialBar {
+        pattern: String,
+        length: Beats,
+        time_signature: TimeSignature,
+        span: Span,
+    },
+}
+
+impl SemanticWarning {
+    pub fn span(&self) -> Span {
+        match self {
+            SemanticWarning::Unused { span, .. } | SemanticWarning::PartialBar { span, .. } => {
+                *span
+            }
+        }
+    }
+}
 
 pub struct Semantic {
     patterns: HashMap<String, Pattern>,
     sections: HashMap<String, Section>,
     instruments: HashMap<String, Instrument>,
     songs: HashMap<String, Song>,
+    /// Duplicates found while collecting the definitions. Only the first
+    /// definition of a name is kept in the maps above.
+    definition_errors: Vec<SemanticError>,
+    warnings: Vec<SemanticWarning>,
+}
+
+fn duplicate(kind: DefinitionKind, name: &str, span: Span, first: Span) -> SemanticError {
+    SemanticError::DuplicateDefinition {
+        kind,
+        name: name.to_string(),
+        span,
+        first,
+    }
+}
+
+fn check_tempo(bpm: u16, span: Span, errors: &mut Vec<SemanticError>) {
+    if !(MIN_TEMPO..=MAX_TEMPO).contains(&bpm) {
+        errors.push(SemanticError::InvalidTempo { bpm, span });
+    }
+}
+
+fn check_time_signature(
+    time_signature: Option<(TimeSignature, Span)>,
+    errors: &mut Vec<SemanticError>,
+) {
+    if let Some((time_signature, span)) = time_signature
+        && !time_signature.is_valid()
+    {
+        errors.push(SemanticError::InvalidTimeSignature {
+            time_signature,
+            span,
+        });
+    }
+}
+
+/// What `param` of `pattern` is used as, described as the kind of argument
+/// that fits it, if `arg` does not fit.
+fn argument_mismatch(pattern: &Pattern, param: &str, arg: &Argument) -> Option<&'static str> {
+    let is_param = |velocity: &Option<Value<u8>>| matches!(velocity, Some(Value::Parameter(name)) if name == param);
+    pattern.events.iter().find_map(|event| match event {
+        PatternEvent::Note { chord, .. } if chord == param => match arg {
+            Argument::Symbol(symbol) if chord::parse(symbol).is_ok() => None,
+            _ => Some("a chord"),
+        },
+        PatternEvent::Pitch { pitch, .. } if pitch == param => match arg {
+            Argument::Symbol(symbol) if chord::parse_pitch(symbol).is_ok() => None,
+            _ => Some("a pitch"),
+        },
+        PatternEvent::Note { velocity, .. } | PatternEvent::Pitch { velocity, .. }
+            if is_param(velocity) =>
+        {
+            match arg {
+                Argument::Number(number) if (1..=127).contains(number) => None,
+                _ => Some("a velocity from 1 to 127"),
+            }
+        }
+        _ => None,
+    })
+}
+
+/// What is wrong with passing `arg` for `param` of `pattern`, if anything:
+/// an argument that does not fit how the parameter is used, or a chord that
+/// cannot be voiced the way the pattern plays it.
+fn argument_error(
+    pattern: &Pattern,
+    param: &str,
+    arg: &Argument,
+    span: Span,
+) -> Option<SemanticError> {
+    if let Some(expected) = argument_mismatch(pattern, param, arg) {
+        return Some(SemanticError::InvalidArgument {
+            pattern: pattern.name.clone(),
+            parameter: param.to_string(),
+            argument: arg.to_string(),
+            expected,
+            span,
+        });
+    }
+    let Argument::Symbol(symbol) = arg else {
+        return None;
+    };
+    pattern.events.iter().find_map(|event| match event {
+        PatternEvent::Note { chord, voicing, .. } if chord == param => {
+            let reason = chord::parse(symbol).ok()?.voice(voicing).err()?;
+            Some(SemanticError::InvalidVoicing {
+                pattern: pattern.name.clone(),
+                chord: symbol.clone(),
+                reason,
+                span,
+            })
+        }
+        _ => None,
+    })
+}
+
+fn is_parameter(params: &[(String, Span)], arg: &Argument) -> bool {
+    matches!(arg, Argument::Symbol(symbol) if params.iter().any(|(param, _)| param == symbol))
+}
+
+fn check_parameters(
+    kind: DefinitionKind,
+    name: &str,
+    params: &[(String, Span)],
+    errors: &mut Vec<SemanticError>,
+) {
+    let mut seen: HashSet<&str> = HashSet::new();
+    for (param, span) in params {
+        if !seen.insert(param) {
+            errors.push(SemanticError::DuplicateParameter {
+                kind,
+                name: name.to_string(),
+                parameter: param.clone(),
+                span: *span,
+            });
+        }
+    }
+}
+
+/// Checks that a call passes one argument per declared parameter.
+fn check_argument_count(
+    kind: DefinitionKind,
+    name: &str,
+    params: &[(String, Span)],
+    args: &[(Argument, Span)],
+    span: Span,
+    errors: &mut Vec<SemanticError>,
+) -> bool {
+    if args.len() == params.len() {
+        return true;
+    }
+    errors.push(SemanticError::WrongArgumentCount {
+        kind,
+        name: name.to_string(),
+        expected: params.len(),
+        found: args.len(),
+        span,
+    });
+    false
+}
+
+/// Checks that `call` passes `pattern` one fitting argument per parameter.
+/// Arguments naming one of the calling section's `section_params` are
+/// checked where the section is called.
+fn check_arguments(
+    pattern: &Pattern,
+    call: &PatternCall,
+    section_params: &[(String, Span)],
+    errors: &mut Vec<SemanticError>,
+) {
+    let kind = DefinitionKind::Pattern;
+    if !check_argument_count(
+        kind,
+        &call.name,
+        &pattern.params,
+        &call.args,
+        call.span,
+        errors,
+    ) {
+        return;
+    }
+    for ((param, _), (arg, span)) in pattern.params.iter().zip(&call.args) {
+        if is_parameter(section_params, arg) {
+            continue;
+        }
+        errors.extend(argument_error(pattern, param, arg, *span));
+    }
+}
+
+/// Checks that `transforms` apply to `pattern` called with `args` and keep
+/// every note it plays in the MIDI range, reporting problems at `span`.
+/// Notes that are not valid to begin with, or come from a parameter without
+/// a literal argument, are left to the other checks.
+fn check_transforms(
+    pattern: &Pattern,
+    args: &[(Argument, Span)],
+    transforms: &[(Transform, Span)],
+    span: Span,
+    errors: &mut Vec<SemanticError>,
+) {
+    let transposed = |note: String, semitones: i32| SemanticError::TransposedOutOfRange {
+        pattern: pattern.name.clone(),
+        semitones,
+        note,
+        span,
+    };
+    let bound = pattern.bind(args);
+    let (transformed, semitones) = match transform::apply(&bound, transforms) {
+        Ok(applied) => applied,
+        Err(TransformError::TransposedOutOfRange { note, semitones }) => {
+            errors.push(transposed(note, semitones));
+            return;
+        }
+        Err(reason) => {
+            errors.push(SemanticError::InvalidTransform {
+                pattern: pattern.name.clone(),
+                reason,
+                span,
+            });
+            return;
+        }
+    };
+    if semitones == 0 {
+        return;
+    }
+    let out_of_range = transformed.events.iter().find_map(|event| match event {
+        PatternEvent::Note { chord, voicing, .. } => {
+            let parsed = chord::parse(chord).ok()?;
+            parsed.voice(voicing).ok()?;
+            let transposed = parsed
+                .transposed(semitones)
+                .and_then(|parsed| parsed.voice(voicing));
+            transposed.is_err().then(|| chord.clone())
+        }
+        _ => None,
+    });
+    if let Some(note) = out_of_range {
+        errors.push(transposed(note, semitones));
+    }
+}
+
+fn check_repeats<T>(sequence: &[Sequence<T>], errors: &mut Vec<SemanticError>) {
+    for item in sequence {
+        if let Sequence::Repeat { count, body, span } = item {
+            if *count == 0 {
+                errors.push(SemanticError::InvalidRepeat { span: *span });
+            }
+            check_repeats(body, errors);
+        }
+    }
+}
+
+/// How long `sequence` plays for, given the length of each call, without
+/// playing its repeats out. `None` once it gets too long to count.
+fn sequence_length<T>(
+    sequence: &[Sequence<T>],
+    call_length: &impl Fn(&T) -> Option<Beats>,
+) -> Option<Beats> {
+    sequence.iter().try_fold(Beats::ZERO, |total, item| {
+        let length = match item {
+            Sequence::Call(call) => call_length(call)?,
+            Sequence::Repeat { count, body, .. } => {
+                sequence_length(body, call_length)?.checked_times(u64::from(*count))?
+            }
+        };
+        total.checked_add(length)
+    })
+}
+
+/// Checks that every chord `pattern` leaves to smooth voice leading, when
+/// called with `args` and `transforms`, has a voicing within the range of
+/// `voice_leading`, reporting the first that does not at `span`. Chords that
+/// are not valid, or do not apply, are left to the other checks.
+fn check_voice_range(
+    pattern: &Pattern,
+    args: &[(Argument, Span)],
+    transforms: &[(Transform, Span)],
+    voice_leading: &VoiceLeading,
+    span: Span,
+    errors: &mut Vec<SemanticError>,
+) {
+    // Ranges that are not valid are reported by `check_voice_leading`.
+    let Some(range) = midigen::voice_range(voice_leading)
+        .filter(|(low, high)| u16::from(*low) + 12 <= u16::from(*high))
+    else {
+        return;
+    };
+    let bound = pattern.bind(args);
+    let Ok((transformed, semitones)) = transform::apply(&bound, transforms) else {
+        return;
+    };
+    let outside = transformed.events.iter().find_map(|event| match event {
+        PatternEvent::Note { chord, voicing, .. }
+            if voicing.octave.is_none() && voicing.inversion.is_none() =>
+        {
+            let parsed = chord::parse(chord).ok()?.transposed(semitones).ok()?;
+            (parsed.inversion.is_none()
+                && chord::lead_voices(&parsed, voicing.spread, &[], range).is_none())
+            .then(|| chord.clone())
+        }
+        _ => None,
+    });
+    if let Some(chord) = outside {
+        errors.push(SemanticError::ChordOutsideVoiceRange {
+            pattern: pattern.name.clone(),
+            chord,
+            low: chord::pitch_name(range.0),
+            high: chord::pitch_name(range.1),
+            span,
+        });
+    }
+}
+
+fn check_voice_leading(
+    voice_leading: &Option<(VoiceLeading, Span)>,
+    errors: &mut Vec<SemanticError>,
+) {
+    if let Some((
+        VoiceLeading::Smooth {
+            range: Some((low, high)),
+        },
+        span,
+    )) = voice_leading
+    {
+        let valid = match (chord::parse_pitch(low), chord::parse_pitch(high)) {
+            (Ok(low), Ok(high)) => u16::from(low) + 12 <= u16::from(high),
+            _ => false,
+        };
+        if !valid {
+            errors.push(SemanticError::InvalidVoiceRange {
+                low: low.clone(),
+                high: high.clone(),
+                span: *span,
+            });
+        }
+    }
 }
 
 impl Semantic {
     pub fn new(results: Vec<TopLevel>) -> Self {
-        let mut patterns = HashMap::new();
-        let mut sections = HashMap::new();
-        let mut instruments = HashMap::new();
-        let mut songs = HashMap::new();
+        let mut patterns: HashMap<String, Pattern> = HashMap::new();
+        let mut sections: HashMap<String, Section> = HashMap::new();
+        let mut instruments: HashMap<String, Instrument> = HashMap::new();
+        let mut songs: HashMap<String, Song> = HashMap::new();
+        let mut errors = vec![];
 
         for res in results {
             match res {
-                TopLevel::Song(song) => {
-                    if songs.contains_key(&song.name) {
-                        panic!("Song with name {:?} defined more then once.", &song.name);
+                TopLevel::Song(song) => match songs.get(&song.name) {
+                    Some(first) => errors.push(duplicate(
+                        DefinitionKind::Song,
+                        &song.name,
+                        song.span,
+                        first.span,
+                    )),
+                    None => {
+                        songs.insert(song.name.clone(), song);
                     }
-                    songs.insert(song.name.clone(), song.clone());
-                }
-                TopLevel::Pattern(pattern) => {
-                    if patterns.contains_key(&pattern.name) {
-                        panic!(
-                            "Pattern with name {:?} defined more then once.",
-                            &pattern.name
-                        );
+                },
+                TopLevel::Pattern(pattern) => match patterns.get(&pattern.name) {
+                    Some(first) => errors.push(duplicate(
+                        DefinitionKind::Pattern,
+                        &pattern.name,
+                        pattern.span,
+                        first.span,
+                    )),
+                    None => {
+                        patterns.insert(pattern.name.clone(), pattern);
                     }
-                    patterns.insert(pattern.name.clone(), pattern.clone());
-                }
-                TopLevel::Section(section) => {
-                    if sections.contains_key(&section.name) {
-                        panic!(
-                            "Section with name {:?} defined more then once.",
-                            &section.name
-                        );
+                },
+                TopLevel::Section(section) => match sections.get(&section.name) {
+                    Some(first) => errors.push(duplicate(
+                        DefinitionKind::Section,
+                        &section.name,
+                        section.span,
+                        first.span,
+                    )),
+                    None => {
+                        sections.insert(section.name.clone(), section);
                     }
-                    sections.insert(section.name.clone(), section.clone());
-                }
-                TopLevel::Instrument(instrument) => {
-                    if instruments.contains_key(&instrument.name) {
-                        panic!(
-                            "Instrument with name {:?} defined more then once.",
-                            &instrument.name
-                        );
+                },
+                TopLevel::Instrument(instrument) => match instruments.get(&instrument.name) {
+                    Some(first) => errors.push(duplicate(
+                        DefinitionKind::Instrument,
+                        &instrument.name,
+                        instrument.span,
+                        first.span,
+                    )),
+                    None => {
+                        instruments.insert(instrument.name.clone(), instrument);
                     }
-                    instruments.insert(instrument.name.clone(), instrument.clone());
-                }
+                },
             }
         }
 
@@ -63,64 +662,522 @@ impl Semantic {
             sections,
             instruments,
             songs,
+            definition_errors: errors,
+            warnings: vec![],
         }
     }
 
-    pub fn analyze(&mut self) -> Result {
-        self.analyze_patterns().unwrap();
-        self.analyze_sections().unwrap();
-        self.analyze_instruments().unwrap();
-        self.analyze_songs().unwrap();
+    /// Runs every check and returns all errors found, ordered by their
+    /// position in the source. Warnings are available from [`Self::warnings`]
+    /// afterwards, whether or not there were errors.
+    pub fn analyze(&mut self) -> Result<(), Vec<SemanticError>> {
+        let mut errors = self.definition_errors.clone();
+        self.analyze_patterns(&mut errors);
+        self.analyze_sections(&mut errors);
+        self.analyze_instruments(&mut errors);
+        self.analyze_songs(&mut errors);
+
+        let mut warnings = self.analyze_reachability();
+        warnings.extend(self.analyze_bars());
+        warnings.sort_by_key(|w| w.span().start);
+        self.warnings = warnings;
 
-        Result::Ok(())
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            errors.sort_by_key(|e| e.span().start);
+            Err(errors)
+        }
     }
 
-    fn analyze_patterns(&mut self) -> Result {
-        Result::Ok(())
+    fn analyze_patterns(&self, errors: &mut Vec<SemanticError>) {
+        for (name, pattern) in &self.patterns {
+            check_voice_leading(&pattern.voice_leading, errors);
+            check_parameters(DefinitionKind::Pattern, name, &pattern.params, errors);
+            let params: HashSet<&str> = pattern.params.iter().map(|(p, _)| p.as_str()).collect();
+            for event in &pattern.events {
+                let (first, last) = event.duration();
+                if first == 0 || last < first {
+                    errors.push(SemanticError::InvalidSteps {
+                        first,
+                        last,
+                        span: event.span(),
+                    });
+                }
+                if let PatternEvent::Note {
+                    velocity: Some(velocity),
+                    ..
+                }
+                | PatternEvent::Pitch {
+                    velocity: Some(velocity),
+                    ..
+                } = event
+                {
+                    match velocity {
+                        Value::Literal(velocity) if !(1..=127).contains(velocity) => {
+                            errors.push(SemanticError::InvalidVelocity {
+                                velocity: *velocity,
+                                span: event.span(),
+                            });
+                        }
+                        Value::Parameter(param) if !params.contains(param.as_str()) => {
+                            errors.push(SemanticError::UndefinedParameter {
+                                pattern: name.clone(),
+                                parameter: param.clone(),
+                                span: event.span(),
+                            });
+                        }
+                        _ => {}
+                    }
+                }
+                match event {
+                    // Arguments for parameters are checked at the calls.
+                    PatternEvent::Note { chord: symbol, .. }
+                    | PatternEvent::Pitch { pitch: symbol, .. }
+                        if params.contains(symbol.as_str()) => {}
+                    PatternEvent::Note {
+                        chord,
+                        voicing,
+                        span,
+                        ..
+                    } => match chord::parse(chord) {
+                        Ok(parsed) => {
+                            if let Err(reason) = parsed.voice(voicing) {
+                                errors.push(SemanticError::InvalidVoicing {
+                                    pattern: name.clone(),
+                                    chord: chord.clone(),
+                                    reason,
+                                    span: *span,
+                                });
+                            }
+                        }
+                        Err(reason) => errors.push(SemanticError::InvalidChord {
+                            pattern: name.clone(),
+                            chord: chord.clone(),
+                            reason,
+                            span: *span,
+                        }),
+                    },
+                    PatternEvent::Pitch { pitch, span, .. } => {
+                        if let Err(reason) = chord::parse_pitch(pitch) {
+                            errors.push(SemanticError::InvalidPitch {
+                                pattern: name.clone(),
+                                pitch: pitch.clone(),
+                                reason,
+                                span: *span,
+                            });
+                        }
+                    }
+                    PatternEvent::Hairpin {
+                        kind,
+                        from: Some(from),
+                        to,
+                        span,
+                        ..
+                    } => {
+                        let valid = match kind {
+                            HairpinKind::Crescendo => from < to,
+                            HairpinKind::Diminuendo => from > to,
+                        };
+                        if !valid {
+                            errors.push(SemanticError::InvalidHairpin {
+                                kind: *kind,
+                                from: *from,
+                                to: *to,
+                                span: *span,
+                            });
+                        }
+                    }
+                    PatternEvent::Wait {
+                        bars: Some(0),
+                        span,
+                        ..
+                    } => errors.push(SemanticError::EmptyRest { span: *span }),
+                    PatternEvent::Tempo { bpm, span, .. } => check_tempo(*bpm, *span, errors),
+                    _ => {}
+                }
+            }
+        }
     }
 
-    fn analyze_sections(&mut self) -> Result {
-        for section in &self.sections {
-            let name = section.0;
-            let section = section.1;
-            let mut channels: HashSet<String> = HashSet::new();
+    fn analyze_sections(&self, errors: &mut Vec<SemanticError>) {
+        for (name, section) in &self.sections {
+            if let Some((bpm, span)) = section.tempo {
+                check_tempo(bpm, span, errors);
+            }
+            check_time_signature(section.time_signature, errors);
+            check_parameters(DefinitionKind::Section, name, &section.params, errors);
+            let mut channels: HashMap<&str, Span> = HashMap::new();
             for part in &section.channels {
-                if channels.contains(&part.name) {
-                    panic!(
-                        "Song {:?} contains a section named {:?}, that's defined more then once.",
-                        name, part.name
-                    )
+                if let Some(first) = channels.get(part.name.as_str()) {
+                    errors.push(SemanticError::DuplicateChannel {
+                        section: name.clone(),
+                        channel: part.name.clone(),
+                        span: part.span,
+                        first: *first,
+                    });
+                    continue;
+                }
+                channels.insert(&part.name, part.span);
+            }
+
+            for channel in &section.channels {
+                check_voice_leading(&channel.voice_leading, errors);
+                check_repeats(&channel.pattern_calls, errors);
+                if let Some((instrument, span)) = &channel.instrument
+                    && !self.instruments.contains_key(instrument)
+                {
+                    errors.push(SemanticError::UndefinedInstrument {
+                        section: name.clone(),
+                        channel: channel.name.clone(),
+                        instrument: instrument.clone(),
+                        span: *span,
+                    });
+                }
+                for call in channel.pattern_calls.iter().flat_map(Sequence::calls) {
+                    match self.patterns.get(&call.name) {
+                        Some(pattern) => {
+                            check_arguments(pattern, call, &section.params, errors);
+                            check_transforms(
+                                pattern,
+                                &call.args,
+                                &call.transforms,
+                                call.span,
+                                errors,
+                            );
+                            if let Some((leading, _)) = pattern
+                                .voice_leading
+                                .as_ref()
+                                .or(channel.voice_leading.as_ref())
+                            {
+                                check_voice_range(
+                                    pattern,
+                                    &call.args,
+                                    &call.transforms,
+                                    leading,
+                                    call.span,
+                                    errors,
+                                );
+                            }
+                        }
+                        None => errors.push(SemanticError::UndefinedPattern {
+                            section: name.clone(),
+                            channel: channel.name.clone(),
+                            pattern: call.name.clone(),
+                            span: call.span,
+                        }),
+                    }
                 }
-                channels.insert(part.name.clone());
             }
         }
-        Result::Ok(())
     }
 
-    fn analyze_instruments(&mut self) -> Result {
-        Result::Ok(())
+    fn analyze_instruments(&self, errors: &mut Vec<SemanticError>) {
+        for (name, instrument) in &self.instruments {
+            if gm::program_for(&instrument.type_).is_none() {
+                errors.push(SemanticError::UnknownInstrumentType {
+                    instrument: name.clone(),
+                    type_: instrument.type_.clone(),
+                    span: instrument.span,
+                });
+            }
+        }
     }
 
-    fn analyze_songs(&mut self) -> Result {
+    fn analyze_songs(&self, errors: &mut Vec<SemanticError>) {
         // Should a file always contain a song? It might be a problem in the future due to multi
         // file imports we can create
         // if self.songs.len() == 0 {
         //     panic!("No songs defined");
         // }
-        for song in &self.songs {
-            let name = song.0;
-            let song = song.1;
-
-            for part in &song.entry_sections {
-                if !self.sections.contains_key(part) {
-                    panic!(
-                        "Song {:?} contains a section named {:?}, that was not defined",
-                        name, part
-                    )
+        for (name, song) in &self.songs {
+            if let Some((bpm, span)) = song.tempo {
+                check_tempo(bpm, span, errors);
+            }
+            check_time_signature(song.time_signature, errors);
+            check_repeats(&song.entry_sections, errors);
+            let mut channels: HashSet<&str> = HashSet::new();
+            for call in song.entry_sections.iter().flat_map(Sequence::calls) {
+                match self.sections.get(&call.name) {
+                    Some(section) => {
+                        channels.extend(section.channels.iter().map(|c| c.name.as_str()));
+                        self.check_forwarded_arguments(section, call, errors);
+                        self.check_section_transforms(section, call, errors);
+                    }
+                    None => errors.push(SemanticError::UndefinedSection {
+                        song: name.clone(),
+                        section: call.name.clone(),
+                        span: call.span,
+                    }),
+                }
+            }
+            if channels.len() > MAX_CHANNELS {
+                errors.push(SemanticError::TooManyChannels {
+                    song: name.clone(),
+                    count: channels.len(),
+                    span: song.span,
+                });
+            }
+            let length = self.song_length(song);
+            if length.and_then(Beats::to_ticks).is_none() {
+                errors.push(SemanticError::SongTooLong {
+                    song: name.clone(),
+                    span: song.span,
+                });
+            }
+        }
+    }
+
+    /// How long `song` plays for, each section lasting as long as its
+    /// longest channel. `None` if that is too long to count.
+    fn song_length(&self, song: &Song) -> Option<Beats> {
+        let song_meter = song
+            .time_signature
+            .map_or(TimeSignature::COMMON, |(meter, _)| meter);
+        sequence_length(&song.entry_sections, &|call: &SectionCall| {
+            let Some(section) = self.sections.get(&call.name) else {
+                return Some(Beats::ZERO);
+            };
+            let meter = section
+                .time_signature
+                .map_or(song_meter, |(meter, _)| meter);
+            // Invalid meters are reported on their own and have no bars.
+            if !meter.is_valid() {
+                return Some(Beats::ZERO);
+            }
+            section
+                .channels
+                .iter()
+                .map(|channel| {
+                    sequence_length(&channel.pattern_calls, &|call: &PatternCall| {
+                        let Some(pattern) = self.patterns.get(&call.name) else {
+                            return Some(Beats::ZERO);
+                        };
+                        transform::apply(pattern, &call.transforms)
+                            .map_or(pattern.length_in(meter), |(transformed, _)| {
+                                transformed.length_in(meter)
+                            })
+                    })
+                })
+                .try_fold(Beats::ZERO, |longest, length| Some(longest.max(length?)))
+        })
+    }
+
+    /// Checks the arguments of a song's call to `section`, each against what
+    /// the patterns it is forwarded to use it as. Every argument is reported
+    /// once, for the first pattern it does not fit.
+    fn check_forwarded_arguments(
+        &self,
+        section: &Section,
+        call: &SectionCall,
+        errors: &mut Vec<SemanticError>,
+    ) {
+        let kind = DefinitionKind::Section;
+        if !check_argument_count(
+            kind,
+            &call.name,
+            &section.params,
+            &call.args,
+            call.span,
+            errors,
+        ) {
+            return;
+        }
+        let mut reported: HashSet<usize> = HashSet::new();
+        for pattern_call in section
+            .channels
+            .iter()
+            .flat_map(|c| &c.pattern_calls)
+            .flat_map(Sequence::calls)
+        {
+            let Some(pattern) = self.patterns.get(&pattern_call.name) else {
+                continue;
+            };
+            if pattern.params.len() != pattern_call.args.len() {
+                continue;
+            }
+            for ((param, _), (arg, _)) in pattern.params.iter().zip(&pattern_call.args) {
+                let Argument::Symbol(symbol) = arg else {
+                    continue;
+                };
+                let Some(index) = section.params.iter().position(|(p, _)| p == symbol) else {
+                    continue;
+                };
+                let (forwarded, span) = &call.args[index];
+                if reported.contains(&index) {
+                    continue;
+                }
+                if let Some(error) = argument_error(pattern, param, forwarded, *span) {
+                    reported.insert(index);
+                    errors.push(error);
                 }
             }
         }
+    }
+
+    /// Checks that a song's call to `section` only transposes it, and that
+    /// the transposition keeps every pattern the section plays in the MIDI
+    /// range, on top of the transforms of the pattern call itself.
+    fn check_section_transforms(
+        &self,
+        section: &Section,
+        call: &SectionCall,
+        errors: &mut Vec<SemanticError>,
+    ) {
+        for (transform, span) in &call.transforms {
+            if !matches!(transform, Transform::Transpose(_)) {
+                errors.push(SemanticError::SectionTransform {
+                    section: call.name.clone(),
+                    transform: transform.name(),
+                    span: *span,
+                });
+            }
+        }
+        let transposes: Vec<(Transform, Span)> = call
+            .transforms
+            .iter()
+            .filter(|(transform, _)| matches!(transform, Transform::Transpose(_)))
+            .cloned()
+            .collect();
+        if transposes.is_empty() {
+            return;
+        }
+        for pattern_call in section
+            .channels
+            .iter()
+            .flat_map(|c| &c.pattern_calls)
+            .flat_map(Sequence::calls)
+        {
+            let Some(pattern) = self.patterns.get(&pattern_call.name) else {
+                continue;
+            };
+            let args: Vec<(Argument, Span)> = pattern_call
+                .args
+                .iter()
+                .map(|(arg, span)| match arg {
+                    Argument::Symbol(symbol) => section
+                        .params
+                        .iter()
+                        .position(|(p, _)| p == symbol)
+                        .and_then(|index| call.args.get(index))
+                        .map_or((arg.clone(), *span), |forwarded| forwarded.clone()),
+                    Argument::Number(_) => (arg.clone(), *span),
+                })
+                .collect();
+            let mut transforms = pattern_call.transforms.clone();
+            transforms.extend(transposes.iter().cloned());
+            // Anything but the range of the transposed notes is down to the
+            // pattern call and reported there.
+            let mut found = vec![];
+            check_transforms(pattern, &args, &transforms, call.span, &mut found);
+            errors.extend(
+                found
+                    .into_iter()
+                    .filter(|error| matches!(error, SemanticError::TransposedOutOfRange { .. })),
+            );
+        }
+    }
+
+    /// Walks everything reachable from the songs and warns about the
+    /// definitions that were never reached.
+    fn analyze_reachability(&self) -> Vec<SemanticWarning> {
+        let mut used_sections: HashSet<&str> = HashSet::new();
+        let mut used_patterns: HashSet<&str> = HashSet::new();
+        let mut used_instruments: HashSet<&str> = HashSet::new();
+
+        for song in self.songs.values() {
+            for call in song.entry_sections.iter().flat_map(Sequence::calls) {
+                let Some((name, section)) = self.sections.get_key_value(&call.name) else {
+                    continue;
+                };
+                if !used_sections.insert(name) {
+                    continue;
+                }
+                for channel in &section.channels {
+                    if let Some((instrument, _)) = &channel.instrument {
+                        used_instruments.insert(instrument);
+                    }
+                    used_patterns.extend(
+                        channel
+                            .pattern_calls
+                            .iter()
+                            .flat_map(Sequence::calls)
+                            .map(|c| c.name.as_str()),
+                    );
+                }
+            }
+        }
+
+        let mut warnings = vec![];
+        let unused = |kind, name: &String, span| SemanticWarning::Unused {
+            kind,
+            name: name.clone(),
+            span,
+        };
+        for (name, section) in &self.sections {
+            if !used_sections.contains(name.as_str()) {
+                warnings.push(unused(DefinitionKind::Section, name, section.span));
+            }
+        }
+        for (name, pattern) in &self.patterns {
+            if !used_patterns.contains(name.as_str()) {
+                warnings.push(unused(DefinitionKind::Pattern, name, pattern.span));
+            }
+        }
+        for (name, instrument) in &self.instruments {
+            if !used_instruments.contains(name.as_str()) {
+                warnings.push(unused(DefinitionKind::Instrument, name, instrument.span));
+            }
+        }
+        warnings
+    }
+
+    /// Warns about patterns that do not fill whole bars of the meter they are
+    /// played in, once per pattern and meter.
+    fn analyze_bars(&self) -> Vec<SemanticWarning> {
+        let mut checked: HashSet<(&str, TimeSignature)> = HashSet::new();
+        let mut warnings = vec![];
+
+        for song in self.songs.values() {
+            let song_meter = song
+                .time_signature
+                .map_or(TimeSignature::COMMON, |(meter, _)| meter);
+            for call in song.entry_sections.iter().flat_map(Sequence::calls) {
+                let Some(section) = self.sections.get(&call.name) else {
+                    continue;
+                };
+                let meter = section
+                    .time_signature
+                    .map_or(song_meter, |(meter, _)| meter);
+                if !meter.is_valid() {
+                    continue;
+                }
+                for channel in &section.channels {
+                    for call in channel.pattern_calls.iter().flat_map(Sequence::calls) {
+                        let Some((name, pattern)) = self.patterns.get_key_value(&call.name) else {
+                            continue;
+                        };
+                        let Some(length) = pattern.length_in(meter) else {
+                            continue;
+                        };
+                        if !checked.insert((name, meter)) || meter.is_whole_bars(length) {
+                            continue;
+                        }
+                        warnings.push(SemanticWarning::PartialBar {
+                            pattern: name.clone(),
+                            length,
+                            time_signature: meter,
+                            span: pattern.span,
+                        });
+                    }
+                }
+            }
+        }
+        warnings
+    }
 
-        Result::Ok(())
+    /// Warnings found by the last call to [`Self::analyze`].
+    pub fn warnings(&self) -> &[SemanticWarning] {
+        &self.warnings
     }
 }
@@ -0,0 +1,211 @@
+//! Exact musical time.
+//!
+//! Positions and lengths are kept as fractions of a quarter-note beat and only
+//! turned into MIDI ticks when a track is written. Pattern events are placed
+//! on a grid of sixteenth-note steps: `[a:b]` covers steps `a` through `b`
+//! inclusive, so it starts `(a - 1) / 4` beats into its pattern and ends
+//! `b / 4` beats into it. `[1:4]` is the first quarter note of a pattern and
+//! `[1:8] ... + [9:16] ...` are two half notes filling a 4/4 bar.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, AddAssign, Sub};
+
+/// Ticks per quarter note in the generated MIDI files.
+pub const TICKS_PER_BEAT: u32 = 480;
+
+/// Latest tick a MIDI file can place an event on. Delta times are stored in
+/// 28 bits, so no two events of a track can be further apart than this.
+pub const MAX_TICKS: u32 = 0x0FFF_FFFF;
+
+/// Sixteenth-note steps per quarter-note beat.
+pub const STEPS_PER_BEAT: u64 = 4;
+
+/// A non-negative number of quarter-note beats, as a reduced fraction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Beats {
+    num: u64,
+    den: u64,
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+impl Beats {
+    pub const ZERO: Beats = Beats { num: 0, den: 1 };
+
+    /// `num / den` beats. Panics if `den` is zero.
+    pub fn new(num: u64, den: u64) -> Self {
+        assert!(den != 0, "beat fraction with a zero denominator");
+        let divisor = gcd(num, den);
+        Beats {
+            num: num / divisor,
+            den: den / divisor,
+        }
+    }
+
+    /// Length of `steps` sixteenth-note steps.
+    pub fn from_steps(steps: u32) -> Self {
+        Beats::new(u64::from(steps), STEPS_PER_BEAT)
+    }
+
+    /// Start of a pattern event on 1-based `step`, relative to its pattern.
+    pub fn step_start(step: u8) -> Self {
+        Beats::from_steps(u32::from(step.saturating_sub(1)))
+    }
+
+    /// End of a pattern event whose last step is the 1-based `step`.
+    pub fn step_end(step: u8) -> Self {
+        Beats::from_steps(u32::from(step))
+    }
+
+    pub fn numerator(&self) -> u64 {
+        self.num
+    }
+
+    pub fn denominator(&self) -> u64 {
+        self.den
+    }
+
+    /// Multiplies by `num / den`. Panics if `den` is zero.
+    pub fn scale(self, num: u64, den: u64) -> Self {
+        let scale = Beats::new(num, den);
+        Beats::new(self.num * scale.num, self.den * scale.den)
+    }
+
+    /// `self + other`, or `None` when the fraction does not fit in a `u64`.
+    pub fn checked_add(self, other: Beats) -> Option<Beats> {
+        let num = self
+            .num
+            .checked_mul(other.den)?
+            .checked_add(other.num.checked_mul(self.den)?)?;
+        Some(Beats::new(num, self.den.checked_mul(other.den)?))
+    }
+
+    /// `self` repeated `times` times, or `None` when the fraction does not
+    /// fit in a `u64`.
+    pub fn checked_times(self, times: u64) -> Option<Beats> {
+        Some(Beats::new(self.num.checked_mul(times)?, self.den))
+    }
+
+    /// Position in MIDI ticks at [`TICKS_PER_BEAT`], rounded to the nearest
+    /// tick when the fraction does not fall on one. `None` past
+    /// [`MAX_TICKS`].
+    pub fn to_ticks(self) -> Option<u32> {
+        let ticks = (u128::from(self.num) * u128::from(TICKS_PER_BEAT) * 2 + u128::from(self.den))
+            / (u128::from(self.den) * 2);
+        u32::try_from(ticks)
+            .ok()
+            .filter(|ticks| *ticks <= MAX_TICKS)
+    }
+}
+
+impl Default for Beats {
+    fn default() -> Self {
+        Beats::ZERO
+    }
+}
+
+impl Add for Beats {
+    type Output = Beats;
+
+    fn add(self, other: Beats) -> Beats {
+        Beats::new(
+            self.num * other.den + other.num * self.den,
+            self.den * other.den,
+        )
+    }
+}
+
+impl AddAssign for Beats {
+    fn add_assign(&mut self, other: Beats) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Beats {
+    type Output = Beats;
+
+    /// Panics if `other` is larger than `self`.
+    fn sub(self, other: Beats) -> Beats {
+        Beats::new(
+            self.num * other.den - other.num * self.den,
+            self.den * other.den,
+        )
+    }
+}
+
+impl Ord for Beats {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (u128::from(self.num) * u128::from(other.den))
+            .cmp(&(u128::from(other.num) * u128::from(self.den)))
+    }
+}
+
+impl PartialOrd for Beats {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Beats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fractions_are_reduced() {
+        assert_eq!(Beats::new(8, 4), Beats::new(2, 1));
+        assert_eq!(Beats::from_steps(6).to_string(), "3/2");
+        assert_eq!(Beats::ZERO, Beats::new(0, 7));
+    }
+
+    #[test]
+    fn step_positions() {
+        assert_eq!(Beats::step_start(1), Beats::ZERO);
+        assert_eq!(Beats::step_end(8), Beats::new(2, 1));
+        assert_eq!(Beats::step_start(9), Beats::new(2, 1));
+        assert_eq!(Beats::step_end(16).to_ticks(), Some(1920));
+    }
+
+    #[test]
+    fn arithmetic_and_ordering() {
+        let third = Beats::new(1, 3);
+        assert_eq!(third + third + third, Beats::new(1, 1));
+        assert_eq!(Beats::new(1, 1) - third, Beats::new(2, 3));
+        assert_eq!(Beats::new(3, 2).scale(2, 3), Beats::new(1, 1));
+        assert!(Beats::new(2, 3) > Beats::new(3, 5));
+        assert_eq!(Beats::new(1, 2).max(Beats::new(1, 3)), Beats::new(1, 2));
+    }
+
+    #[test]
+    fn ticks_round_to_nearest() {
+        assert_eq!(Beats::new(1, 3).to_ticks(), Some(160));
+        assert_eq!(Beats::new(1, 7).to_ticks(), Some(69)); // 68.57
+        assert_eq!(Beats::new(3, 7).to_ticks(), Some(206)); // 205.71
+    }
+
+    #[test]
+    fn ticks_stop_at_the_end_of_a_midi_file() {
+        let last = Beats::new(u64::from(MAX_TICKS), u64::from(TICKS_PER_BEAT));
+        assert_eq!(last.to_ticks(), Some(MAX_TICKS));
+        assert_eq!(last.checked_add(Beats::new(1, 1)).unwrap().to_ticks(), None);
+        assert_eq!(Beats::new(u64::MAX, 1).to_ticks(), None);
+        assert_eq!(Beats::new(u64::MAX, 1).checked_times(2), None);
+        assert_eq!(Beats::new(u64::MAX, 1).checked_add(Beats::new(1, 2)), None);
+        assert_eq!(Beats::new(3, 2).checked_times(4), Some(Beats::new(6, 1)));
+    }
+}
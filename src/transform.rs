@@ -0,0 +1,563 @@
+use std::borrow::Cow;
+
+use thiserror::Error;
+
+use crate::ast::{Pattern, PatternEvent, Span, Transform};
+use crate::chord::{self, PitchError};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TransformError {
+    #[error("transposing {note:?} by {semitones} semitones leaves the MIDI range 0 to 127")]
+    TransposedOutOfRange { note: String, semitones: i32 },
+    #[error("inverting {note:?} around {around} leaves the MIDI range 0 to 127")]
+    InvertedOutOfRange { note: String, around: String },
+    #[error("{around:?} is not a valid pitch to invert around: {reason}")]
+    InvalidAxis { around: String, reason: PitchError },
+    #[error("`invert` only mirrors single pitches, but the pattern plays chord {chord:?}")]
+    InvertedChord { chord: String },
+    #[error("`{transform}` cannot move a rest of whole bars, give its steps instead")]
+    BarRest { transform: &'static str },
+    #[error("`{transform}` needs a factor of at least 1")]
+    ZeroFactor { transform: &'static str },
+    #[error("diminishing by {factor} would leave steps [{first}:{last}] between two steps")]
+    UnevenDiminution { factor: u8, first: u8, last: u8 },
+    #[error("diminishing by {factor} would leave a rest of {bars} bars between two bars")]
+    UnevenBarRest { factor: u8, bars: u16 },
+    #[error("rotating by {steps} steps would cut steps [{first}:{last}] in two")]
+    SplitByRotation { steps: i32, first: u8, last: u8 },
+    #[error("the pattern would run past step {}", u8::MAX)]
+    TooLong,
+}
+
+/// Applies `transforms` to the events of `pattern`, one after another.
+/// Pitches are respelled as they move, but chord symbols are not: the
+/// semitones the chords are moved by come back alongside the pattern, for
+/// the caller to voice them with.
+pub fn apply<'p>(
+    pattern: &'p Pattern,
+    transforms: &[(Transform, Span)],
+) -> Result<(Cow<'p, Pattern>, i32), TransformError> {
+    if transforms.is_empty() {
+        return Ok((Cow::Borrowed(pattern), 0));
+    }
+    let mut transformed = pattern.clone();
+    let events = &mut transformed.events;
+    let mut chord_shift = 0;
+    // Transpositions are added up before the pitches are respelled, so
+    // `^ +24 ^ -12` only has to stay in range once both have been applied.
+    let mut pitch_shift = 0;
+    for (transform, span) in transforms {
+        match transform {
+            Transform::Transpose(semitones) => {
+                chord_shift = add_semitones(events, chord_shift, *semitones)?;
+                pitch_shift = add_semitones(events, pitch_shift, *semitones)?;
+            }
+            Transform::Reverse => reverse(events, *span)?,
+            Transform::Augment(factor) => augment(events, *factor)?,
+            Transform::Diminish(factor) => diminish(events, *factor)?,
+            Transform::Invert { around } => {
+                transpose_pitches(events, pitch_shift)?;
+                pitch_shift = 0;
+                invert(events, around)?;
+            }
+            Transform::Rotate(steps) => rotate(events, *steps, *span)?,
+        }
+    }
+    transpose_pitches(events, pitch_shift)?;
+    Ok((Cow::Owned(transformed), chord_shift))
+}
+
+/// `shift` moved by `semitones` more. A transposition past the range of
+/// `i32` takes every note far outside of the MIDI range, so it is reported
+/// for the first one.
+fn add_semitones(
+    events: &[PatternEvent],
+    shift: i32,
+    semitones: i32,
+) -> Result<i32, TransformError> {
+    let Some(sum) = shift.checked_add(semitones) else {
+        let note = events.iter().find_map(|event| match event {
+            PatternEvent::Note { chord: note, .. } | PatternEvent::Pitch { pitch: note, .. } => {
+                Some(note.clone())
+            }
+            _ => None,
+        });
+        return match note {
+            Some(note) => Err(TransformError::TransposedOutOfRange {
+                note,
+                semitones: shift.saturating_add(semitones),
+            }),
+            // Without notes there is nothing to leave the range.
+            None => Ok(shift.saturating_add(semitones)),
+        };
+    };
+    Ok(sum)
+}
+
+fn duration_mut(event: &mut PatternEvent) -> &mut (u8, u8) {
+    match event {
+        PatternEvent::Note { duration, .. }
+        | PatternEvent::Pitch { duration, .. }
+        | PatternEvent::Wait { duration, .. }
+        | PatternEvent::Hairpin { duration, .. }
+        | PatternEvent::Tempo { duration, .. } => duration,
+    }
+}
+
+fn step(step: i32) -> Result<u8, TransformError> {
+    u8::try_from(step).map_err(|_| TransformError::TooLong)
+}
+
+/// Last step of `events`, which reversing and rotating keep the pattern
+/// lasting up to. Rests of whole bars have no step count without a meter.
+fn last_step(events: &[PatternEvent], transform: &'static str) -> Result<u8, TransformError> {
+    if events
+        .iter()
+        .any(|event| matches!(event, PatternEvent::Wait { bars: Some(_), .. }))
+    {
+        return Err(TransformError::BarRest { transform });
+    }
+    Ok(events
+        .iter()
+        .map(|event| event.duration().1)
+        .max()
+        .unwrap_or(0))
+}
+
+/// Adds a rest on `last` when no event reaches it any more, so the pattern
+/// keeps its length.
+fn pad_to(events: &mut Vec<PatternEvent>, last: u8, span: Span) {
+    if events.iter().all(|event| event.duration().1 < last) {
+        events.push(PatternEvent::Wait {
+            bars: None,
+            duration: (last, last),
+            span,
+        });
+    }
+}
+
+/// Splits off the events that reversing and rotating move: notes, pitches
+/// and rests. Tempo changes and hairpins stay on the steps they are written
+/// at, and come back second.
+fn split_played(events: &mut Vec<PatternEvent>) -> (Vec<PatternEvent>, Vec<PatternEvent>) {
+    events.drain(..).partition(|event| {
+        matches!(
+            event,
+            PatternEvent::Note { .. } | PatternEvent::Pitch { .. } | PatternEvent::Wait { .. }
+        )
+    })
+}
+
+/// Puts the `moved` and `kept` events back together in the order they start.
+fn rejoin(events: &mut Vec<PatternEvent>, mut moved: Vec<PatternEvent>, kept: Vec<PatternEvent>) {
+    moved.extend(kept);
+    moved.sort_by_key(|event| event.duration().0);
+    *events = moved;
+}
+
+fn reverse(events: &mut Vec<PatternEvent>, span: Span) -> Result<(), TransformError> {
+    let last = last_step(events, "reverse")?;
+    let end = i32::from(last) + 1;
+    let (mut played, kept) = split_played(events);
+    for event in played.iter_mut() {
+        let (first, final_step) = *duration_mut(event);
+        *duration_mut(event) = (
+            step(end - i32::from(final_step))?,
+            step(end - i32::from(first))?,
+        );
+    }
+    played.reverse();
+    rejoin(events, played, kept);
+    pad_to(events, last, span);
+    Ok(())
+}
+
+fn augment(events: &mut [PatternEvent], factor: u8) -> Result<(), TransformError> {
+    if factor == 0 {
+        return Err(TransformError::ZeroFactor {
+            transform: "augment",
+        });
+    }
+    let factor = i32::from(factor);
+    for event in events.iter_mut() {
+        let (first, last) = *duration_mut(event);
+        let first = step((i32::from(first) - 1) * factor + 1)?;
+        if let PatternEvent::Wait {
+            bars: Some(bars),
+            duration,
+            ..
+        } = event
+        {
+            *bars = bars
+                .checked_mul(factor as u16)
+                .ok_or(TransformError::TooLong)?;
+            *duration = (first, first);
+        } else {
+            *duration_mut(event) = (first, step(i32::from(last) * factor)?);
+        }
+    }
+    Ok(())
+}
+
+fn diminish(events: &mut [PatternEvent], factor: u8) -> Result<(), TransformError> {
+    if factor == 0 {
+        return Err(TransformError::ZeroFactor {
+            transform: "diminish",
+        });
+    }
+    for event in events.iter_mut() {
+        let (first, last) = *duration_mut(event);
+        let uneven = TransformError::UnevenDiminution {
+            factor,
+            first,
+            last,
+        };
+        if first == 0 || (first - 1) % factor != 0 {
+            return Err(uneven);
+        }
+        let first = (first - 1) / factor + 1;
+        if let PatternEvent::Wait {
+            bars: Some(bars),
+            duration,
+            ..
+        } = event
+        {
+            if *bars % u16::from(factor) != 0 {
+                return Err(TransformError::UnevenBarRest {
+                    factor,
+                    bars: *bars,
+                });
+            }
+            *bars /= u16::from(factor);
+            *duration = (first, first);
+        } else if last % factor != 0 {
+            return Err(uneven);
+        } else {
+            *duration_mut(event) = (first, last / factor);
+        }
+    }
+    Ok(())
+}
+
+/// Moves the first `steps` steps of notes and rests to the end. Rests that
+/// are cut in two by that become two rests; any other event that is cut is
+/// an error.
+fn rotate(events: &mut Vec<PatternEvent>, steps: i32, span: Span) -> Result<(), TransformError> {
+    let last = last_step(events, "rotate")?;
+    if last == 0 {
+        return Ok(());
+    }
+    let cut = steps.rem_euclid(i32::from(last));
+    let length = i32::from(last);
+    let (played, kept) = split_played(events);
+    let mut rotated = Vec::with_capacity(played.len());
+    for mut event in played {
+        let (first, final_step) = event.duration();
+        let (first, final_step) = (i32::from(first), i32::from(final_step));
+        if final_step <= cut {
+            *duration_mut(&mut event) = (
+                step(first - cut + length)?,
+                step(final_step - cut + length)?,
+            );
+        } else if first > cut {
+            *duration_mut(&mut event) = (step(first - cut)?, step(final_step - cut)?);
+        } else if let PatternEvent::Wait { span, .. } = event {
+            rotated.push(PatternEvent::Wait {
+                bars: None,
+                duration: (step(first - cut + length)?, last),
+                span,
+            });
+            *duration_mut(&mut event) = (1, step(final_step - cut)?);
+        } else {
+            return Err(TransformError::SplitByRotation {
+                steps,
+                first: first as u8,
+                last: final_step as u8,
+            });
+        }
+        rotated.push(event);
+    }
+    rejoin(events, rotated, kept);
+    pad_to(events, last, span);
+    Ok(())
+}
+
+fn invert(events: &mut [PatternEvent], around: &str) -> Result<(), TransformError> {
+    let axis = chord::parse_pitch(around).map_err(|reason| TransformError::InvalidAxis {
+        around: around.to_string(),
+        reason,
+    })?;
+    for event in events.iter_mut() {
+        match event {
+            PatternEvent::Note { chord, .. } => {
+                return Err(TransformError::InvertedChord {
+                    chord: chord.clone(),
+                });
+            }
+            PatternEvent::Pitch { pitch, .. } => {
+                // Pitches that are not valid are left to semantic analysis.
+                let Ok(number) = chord::parse_pitch(pitch) else {
+                    continue;
+                };
+                let inverted = 2 * i32::from(axis) - i32::from(number);
+                *pitch = u8::try_from(inverted)
+                    .ok()
+                    .filter(|inverted| *inverted <= 127)
+                    .map(chord::pitch_name)
+                    .ok_or_else(|| TransformError::InvertedOutOfRange {
+                        note: pitch.clone(),
+                        around: around.to_string(),
+                    })?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn transpose_pitches(events: &mut [PatternEvent], semitones: i32) -> Result<(), TransformError> {
+    if semitones == 0 {
+        return Ok(());
+    }
+    for event in events.iter_mut() {
+        if let PatternEvent::Pitch { pitch, .. } = event
+            && let Ok(number) = chord::parse_pitch(pitch)
+        {
+            let transposed = i32::from(number).saturating_add(semitones);
+            *pitch = u8::try_from(transposed)
+                .ok()
+                .filter(|transposed| *transposed <= 127)
+                .map(chord::pitch_name)
+                .ok_or_else(|| TransformError::TransposedOutOfRange {
+                    note: pitch.clone(),
+                    semitones,
+                })?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::TopLevel;
+    use crate::lexer;
+    use crate::parser::Parser;
+
+    /// Events as their pitch, chord or `rest`, each with its steps.
+    type Played = Vec<(String, (u8, u8))>;
+
+    /// What the pattern `body` plays once the `transforms` written after a
+    /// call to it are applied: every event as its pitch, chord, `rest`,
+    /// `tempo` or `hairpin`,
+    /// with its steps, and the semitones chords are moved by.
+    fn transformed(body: &str, transforms: &str) -> Result<(Played, i32), TransformError> {
+        let source = format!(
+            "Pattern motif():\n\treturn {body}\nSection S:\n\tChannel c:\n\t\treturn motif(){transforms}"
+        );
+        let ast = Parser::new(lexer::tokenize(&source)).parse().unwrap();
+        let (TopLevel::Pattern(pattern), TopLevel::Section(section)) = (&ast[0], &ast[1]) else {
+            panic!("expected a pattern and a section");
+        };
+        let call = section.channels[0].pattern_calls[0].calls()[0];
+        let (pattern, chord_shift) = apply(pattern, &call.transforms)?;
+        let events = pattern
+            .events
+            .iter()
+            .map(|event| {
+                let label = match event {
+                    PatternEvent::Note { chord, .. } => chord.clone(),
+                    PatternEvent::Pitch { pitch, .. } => pitch.clone(),
+                    PatternEvent::Wait {
+                        bars: Some(bars), ..
+                    } => format!("rest {bars} bars"),
+                    PatternEvent::Wait { .. } => "rest".to_string(),
+                    PatternEvent::Tempo { bpm, .. } => format!("tempo {bpm}"),
+                    PatternEvent::Hairpin { .. } => "hairpin".to_string(),
+                };
+                (label, event.duration())
+            })
+            .collect();
+        Ok((events, chord_shift))
+    }
+
+    fn events(events: &[(&str, (u8, u8))]) -> Played {
+        events
+            .iter()
+            .map(|(label, steps)| (label.to_string(), *steps))
+            .collect()
+    }
+
+    #[test]
+    fn reverse_plays_back_to_front() {
+        let motif = "[1:4] Pitch(C4) + [5:6] Pitch(D4) + [9:12] Pitch(E4)";
+        let expected = events(&[("E4", (1, 4)), ("D4", (7, 8)), ("C4", (9, 12))]);
+        assert_eq!(transformed(motif, ".reverse()"), Ok((expected.clone(), 0)));
+        assert_eq!(transformed(motif, ".retrograde()"), Ok((expected, 0)));
+        assert_eq!(
+            transformed("[3:4] Pitch(C4) + [5:8] Pitch(D4)", ".reverse()"),
+            Ok((
+                events(&[("D4", (1, 4)), ("C4", (5, 6)), ("rest", (8, 8))]),
+                0
+            ))
+        );
+        assert_eq!(
+            transformed("[1:4] Pitch(C4) + [5] Wait(1 bar)", ".reverse()"),
+            Err(TransformError::BarRest {
+                transform: "reverse"
+            })
+        );
+    }
+
+    #[test]
+    fn augment_and_diminish_scale_the_steps() {
+        let motif = "[1:2] Pitch(C4) + [3:4] Pitch(D4) + [5] Wait(2 bars)";
+        let augmented = events(&[("C4", (1, 4)), ("D4", (5, 8)), ("rest 4 bars", (9, 9))]);
+        assert_eq!(transformed(motif, ".augment(2)"), Ok((augmented, 0)));
+        assert_eq!(
+            transformed(motif, ".augment(2).diminish(2)"),
+            Ok((
+                events(&[("C4", (1, 2)), ("D4", (3, 4)), ("rest 2 bars", (5, 5))]),
+                0
+            ))
+        );
+        assert_eq!(
+            transformed("[1:2] Pitch(C4) + [4:5] Pitch(D4)", ".diminish(2)"),
+            Err(TransformError::UnevenDiminution {
+                factor: 2,
+                first: 4,
+                last: 5
+            })
+        );
+        assert_eq!(
+            transformed("[1:2] Pitch(C4)", ".augment(0)"),
+            Err(TransformError::ZeroFactor {
+                transform: "augment"
+            })
+        );
+        assert_eq!(
+            transformed("[1:128] Pitch(C4)", ".augment(2)"),
+            Err(TransformError::TooLong)
+        );
+    }
+
+    #[test]
+    fn rotate_moves_steps_around() {
+        let motif = "[1:4] Pitch(C4) + [5:8] Pitch(D4) + [9:16] Wait()";
+        assert_eq!(
+            transformed(motif, ".rotate(4)"),
+            Ok((
+                events(&[("D4", (1, 4)), ("rest", (5, 12)), ("C4", (13, 16))]),
+                0
+            ))
+        );
+        assert_eq!(
+            transformed(motif, ".rotate(-4)"),
+            Ok((
+                events(&[
+                    ("rest", (1, 4)),
+                    ("C4", (5, 8)),
+                    ("D4", (9, 12)),
+                    ("rest", (13, 16)),
+                ]),
+                0
+            ))
+        );
+        assert_eq!(
+            transformed(motif, ".rotate(2)"),
+            Err(TransformError::SplitByRotation {
+                steps: 2,
+                first: 1,
+                last: 4
+            })
+        );
+    }
+
+    #[test]
+    fn tempo_changes_and_hairpins_stay_in_place() {
+        let motif = "[1:8] Crescendo(p, f) + [1:4] Pitch(C4) + [5:8] Pitch(D4) + [5:5] Tempo(90)";
+        assert_eq!(
+            transformed(motif, ".reverse()"),
+            Ok((
+                events(&[
+                    ("D4", (1, 4)),
+                    ("hairpin", (1, 8)),
+                    ("C4", (5, 8)),
+                    ("tempo 90", (5, 5)),
+                ]),
+                0
+            ))
+        );
+        assert_eq!(
+            transformed(motif, ".rotate(4)"),
+            Ok((
+                events(&[
+                    ("D4", (1, 4)),
+                    ("hairpin", (1, 8)),
+                    ("C4", (5, 8)),
+                    ("tempo 90", (5, 5)),
+                ]),
+                0
+            ))
+        );
+    }
+
+    #[test]
+    fn invert_mirrors_pitches() {
+        let motif = "[1:4] Pitch(E4) + [5:8] Pitch(D4)";
+        assert_eq!(
+            transformed(motif, ".invert(around=C4)"),
+            Ok((events(&[("G#3", (1, 4)), ("A#3", (5, 8))]), 0))
+        );
+        assert_eq!(
+            transformed("[1:4] Note(Am)", ".invert(around=C4)"),
+            Err(TransformError::InvertedChord {
+                chord: "Am".to_string()
+            })
+        );
+        assert_eq!(
+            transformed(motif, ".invert(around=G8)"),
+            Err(TransformError::InvertedOutOfRange {
+                note: "E4".to_string(),
+                around: "G8".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn transforms_apply_in_order() {
+        assert_eq!(
+            transformed(
+                "[1:2] Pitch(C4) + [3:4] Note(Am)",
+                ".augment(2).transpose(-12)"
+            ),
+            Ok((events(&[("C3", (1, 4)), ("Am", (5, 8))]), -12))
+        );
+        assert_eq!(
+            transformed("[1:4] Pitch(E4)", ".invert(around=C4) ^ +12"),
+            Ok((events(&[("G#4", (1, 4))]), 12))
+        );
+        assert_eq!(
+            transformed("[1:4] Pitch(E4)", " ^ +12.invert(around=C4)"),
+            Ok((events(&[("G#2", (1, 4))]), 12))
+        );
+        assert_eq!(
+            transformed("[1:4] Pitch(C8)", " ^ +24 ^ -12"),
+            Ok((events(&[("C9", (1, 4))]), 12))
+        );
+        assert_eq!(
+            transformed("[1:4] Note(Am)", " ^ +2147483647 ^ +2147483647"),
+            Err(TransformError::TransposedOutOfRange {
+                note: "Am".to_string(),
+                semitones: i32::MAX
+            })
+        );
+        assert_eq!(
+            transformed("[1:4] Pitch(C8)", ".transpose(24)"),
+            Err(TransformError::TransposedOutOfRange {
+                note: "C8".to_string(),
+                semitones: 24
+            })
+        );
+    }
+}
@@ -20,9 +20,9 @@ fn test_full_song_flow() {
 	return Intro() + Intro()
     "#;
 
-    let tokens = lexer::tokenize(&input);
+    let tokens = lexer::tokenize(input);
     let mut parser = Parser::new(tokens);
-    let ast = parser.parse();
+    let ast = parser.parse().unwrap();
 
     assert_eq!(ast.len(), 4);
 }
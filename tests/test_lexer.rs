@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use cricket::lexer::{Token, tokenize};
+    use cricket::lexer::{LexError, Token, lex, tokenize};
 
     #[test]
     fn test_basic_tokenization() {
@@ -27,8 +27,80 @@ mod tests {
     fn test_chord_token() {
         let input = "Note(Am)";
         let tokens = tokenize(input);
-        let values: Vec<_> = tokens.iter().map(|(_, v)| v.as_str()).collect();
+        let values: Vec<_> = tokens.iter().map(|(_, v, _)| v.as_str()).collect();
 
         assert!(values.contains(&"Am"));
     }
+
+    #[test]
+    fn test_token_spans() {
+        let input = "Pattern intro():\n\treturn [1:8] Note(Am)";
+        let tokens = tokenize(input);
+
+        let (token, text, span) = &tokens[11];
+        assert_eq!(*token, Token::Identifier);
+        assert_eq!(text, "Note");
+        assert_eq!((span.start, span.end), (31, 35));
+        assert_eq!((span.line, span.column), (2, 15));
+    }
+
+    #[test]
+    fn test_unexpected_characters_are_reported() {
+        let input = "Note(Am);\nNote(C) ~";
+        let lexed = lex(input);
+
+        let texts: Vec<_> = lexed.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "unexpected character \";\"".to_string(),
+                "unexpected character \"~\"".to_string(),
+            ]
+        );
+        let span = lexed.errors[1].span();
+        assert_eq!((span.line, span.column), (2, 9));
+
+        // The offending characters are skipped, just like `tokenize` does.
+        assert_eq!(lexed.tokens, tokenize(input));
+        assert_eq!(lexed.tokens.len(), 8);
+    }
+
+    #[test]
+    fn test_comments_are_kept_as_trivia() {
+        let input = r#"
+            // Verse groove, bars 1-2
+            Pattern intro(): /* repeats twice */
+                return [1:8] Note(Am)
+        "#;
+        let lexed = lex(input);
+
+        assert!(lexed.errors.is_empty());
+        assert!(lexed.tokens.iter().all(|(tok, _, _)| !tok.is_trivia()));
+        assert_eq!(lexed.tokens[0].0, Token::Pattern);
+
+        let comments: Vec<_> = lexed
+            .trivia
+            .iter()
+            .map(|(tok, text, span)| (tok.clone(), text.as_str(), span.line))
+            .collect();
+        assert_eq!(
+            comments,
+            vec![
+                (Token::LineComment, "// Verse groove, bars 1-2", 2),
+                (Token::BlockComment, "/* repeats twice */", 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let lexed = lex("Pattern intro(): /* never closed\n return");
+
+        assert_eq!(lexed.errors.len(), 1);
+        assert!(matches!(
+            lexed.errors[0],
+            LexError::UnterminatedComment { span } if span.column == 18
+        ));
+        assert_eq!(lexed.tokens.len(), 5);
+    }
 }
@@ -1,9 +1,12 @@
 #[cfg(test)]
 mod tests {
     use cricket::{
-        ast::TopLevel,
+        ast::{
+            Argument, Dynamic, HairpinKind, PatternEvent, Sequence, Span, Spread, TopLevel,
+            Transform, Value, VoiceLeading, Voicing,
+        },
         lexer::{self, Token},
-        parser::Parser,
+        parser::{ParseError, Parser},
     };
 
     #[test]
@@ -16,7 +19,7 @@ mod tests {
 
         let tokens = lexer::tokenize(input);
         let mut parser = Parser::new(tokens);
-        let ast = parser.parse();
+        let ast = parser.parse().unwrap();
 
         assert_eq!(ast.len(), 1);
         match &ast[0] {
@@ -37,7 +40,7 @@ mod tests {
 
         let tokens = lexer::tokenize(input);
         let mut parser = Parser::new(tokens);
-        let ast = parser.parse();
+        let ast = parser.parse().unwrap();
 
         assert_eq!(ast.len(), 1);
         match &ast[0] {
@@ -48,4 +51,770 @@ mod tests {
             _ => panic!("Expected pattern node"),
         }
     }
+
+    #[test]
+    fn test_parse_chord_symbols() {
+        let input = r#"
+            Pattern changes():
+                return [1:4] Note(C#m7) + [5:8] Note(C/E) + [9:12] Note(F#9) + [13:16] Note(Bbm7b5/Ab)
+        "#;
+
+        let tokens = lexer::tokenize(input);
+        let ast = Parser::new(tokens).parse().unwrap();
+        let TopLevel::Pattern(pattern) = &ast[0] else {
+            panic!("Expected pattern node");
+        };
+        let chords: Vec<_> = pattern
+            .events
+            .iter()
+            .map(|event| match event {
+                PatternEvent::Note { chord, .. } => chord.as_str(),
+                _ => panic!("Expected note"),
+            })
+            .collect();
+        assert_eq!(chords, vec!["C#m7", "C/E", "F#9", "Bbm7b5/Ab"]);
+
+        // The parts of a chord symbol cannot be spread out.
+        let tokens = lexer::tokenize("Pattern spaced():\n return [1:4] Note(C #m)");
+        let errors = Parser::new(tokens).parse().unwrap_err();
+        assert!(matches!(
+            &errors[0],
+            ParseError::UnexpectedToken {
+                found: Token::Hash,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_parse_voicing_options() {
+        let input = r#"
+            Pattern comp():
+                return [1:8] Note(Am, octave=3, inversion=1) + [9:16] Note(Cmaj7, voicing=drop2) + [9:16] Note(Am/2)
+        "#;
+
+        let tokens = lexer::tokenize(input);
+        let ast = Parser::new(tokens).parse().unwrap();
+        let TopLevel::Pattern(pattern) = &ast[0] else {
+            panic!("Expected pattern node");
+        };
+        let voicings: Vec<_> = pattern
+            .events
+            .iter()
+            .map(|event| match event {
+                PatternEvent::Note { chord, voicing, .. } => (chord.as_str(), *voicing),
+                _ => panic!("Expected note"),
+            })
+            .collect();
+        assert_eq!(
+            voicings,
+            vec![
+                (
+                    "Am",
+                    Voicing {
+                        octave: Some(3),
+                        inversion: Some(1),
+                        spread: Spread::Close,
+                    }
+                ),
+                (
+                    "Cmaj7",
+                    Voicing {
+                        spread: Spread::Drop2,
+                        ..Voicing::default()
+                    }
+                ),
+                ("Am/2", Voicing::default()),
+            ]
+        );
+
+        let errors = |input: &str| Parser::new(lexer::tokenize(input)).parse().unwrap_err();
+        assert!(matches!(
+            &errors("Pattern p():\n return [1:4] Note(C, octav=3)")[0],
+            ParseError::UnknownOption { name, .. } if name == "octav"
+        ));
+        assert_eq!(
+            errors("Pattern p():\n return [1:4] Note(C, voicing=wide)")[0].to_string(),
+            "\"wide\" is not a valid value for `voicing`"
+        );
+    }
+
+    #[test]
+    fn test_parse_voice_leading() {
+        let input = r#"
+            Pattern verse():
+                voice_leading: none
+                return [1:16] Note(C)
+
+            Section Intro:
+                Channel pad:
+                    voice_leading: smooth
+                    return verse()
+                Channel keys uses piano:
+                    voice_leading: smooth(C3, C5)
+                    return verse()
+        "#;
+
+        let tokens = lexer::tokenize(input);
+        let ast = Parser::new(tokens).parse().unwrap();
+        let TopLevel::Pattern(pattern) = &ast[0] else {
+            panic!("Expected pattern node");
+        };
+        assert_eq!(
+            pattern.voice_leading.as_ref().map(|(leading, _)| leading),
+            Some(&VoiceLeading::None)
+        );
+        let TopLevel::Section(section) = &ast[1] else {
+            panic!("Expected section node");
+        };
+        let leadings: Vec<_> = section
+            .channels
+            .iter()
+            .map(|channel| channel.voice_leading.clone().map(|(leading, _)| leading))
+            .collect();
+        assert_eq!(
+            leadings,
+            vec![
+                Some(VoiceLeading::Smooth { range: None }),
+                Some(VoiceLeading::Smooth {
+                    range: Some(("C3".to_string(), "C5".to_string()))
+                }),
+            ]
+        );
+
+        let errors = Parser::new(lexer::tokenize(
+            "Pattern p():\n voice_leading: tight\n return [1:4] Note(C)",
+        ))
+        .parse()
+        .unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "\"tight\" is not a valid value for `voice_leading`"
+        );
+    }
+
+    #[test]
+    fn test_parse_pitches() {
+        let input = r#"
+            Pattern bass():
+                return [1:4] Pitch(E2) + [5:8] Pitch(C#4) + [9:16] Pitch(Bb2)
+        "#;
+
+        let tokens = lexer::tokenize(input);
+        let ast = Parser::new(tokens).parse().unwrap();
+        let TopLevel::Pattern(pattern) = &ast[0] else {
+            panic!("Expected pattern node");
+        };
+        let pitches: Vec<_> = pattern
+            .events
+            .iter()
+            .map(|event| match event {
+                PatternEvent::Pitch {
+                    pitch, duration, ..
+                } => (pitch.as_str(), *duration),
+                _ => panic!("Expected pitch"),
+            })
+            .collect();
+        assert_eq!(
+            pitches,
+            vec![("E2", (1, 4)), ("C#4", (5, 8)), ("Bb2", (9, 16))]
+        );
+    }
+
+    #[test]
+    fn test_parse_velocity_and_dynamics() {
+        let input = r#"
+            Pattern lead():
+                dynamics: mf
+                return [1:16] Crescendo(p, f) + [1:4] Note(Am, vel=80) + [5:8] Pitch(C4, vel=64) + [9:16] Diminuendo(pp)
+
+            Section Intro:
+                tempo: 90
+                dynamics: ff
+                Channel lead:
+                    return lead()
+        "#;
+
+        let tokens = lexer::tokenize(input);
+        let ast = Parser::new(tokens).parse().unwrap();
+        let TopLevel::Pattern(pattern) = &ast[0] else {
+            panic!("Expected pattern node");
+        };
+        assert_eq!(
+            pattern.dynamics.map(|(dynamic, _)| dynamic),
+            Some(Dynamic::Mf)
+        );
+        assert!(matches!(
+            pattern.events[0],
+            PatternEvent::Hairpin {
+                kind: HairpinKind::Crescendo,
+                from: Some(Dynamic::P),
+                to: Dynamic::F,
+                duration: (1, 16),
+                ..
+            }
+        ));
+        assert!(matches!(
+            pattern.events[1],
+            PatternEvent::Note {
+                velocity: Some(Value::Literal(80)),
+                ..
+            }
+        ));
+        assert!(matches!(
+            pattern.events[2],
+            PatternEvent::Pitch {
+                velocity: Some(Value::Literal(64)),
+                ..
+            }
+        ));
+        assert!(matches!(
+            pattern.events[3],
+            PatternEvent::Hairpin {
+                kind: HairpinKind::Diminuendo,
+                from: None,
+                to: Dynamic::Pp,
+                ..
+            }
+        ));
+        let TopLevel::Section(section) = &ast[1] else {
+            panic!("Expected section node");
+        };
+        assert_eq!(
+            section.dynamics.map(|(dynamic, _)| dynamic),
+            Some(Dynamic::Ff)
+        );
+
+        let errors = |input: &str| Parser::new(lexer::tokenize(input)).parse().unwrap_err();
+        assert_eq!(
+            errors("Pattern p():\n return [1:4] Pitch(C4, octave=3)")[0].to_string(),
+            "unknown `Pitch` option \"octave\", expected `vel`"
+        );
+        assert_eq!(
+            errors("Pattern p():\n dynamics: loud\n return [1:4] Note(C)")[0].to_string(),
+            "\"loud\" is not a valid value for `dynamics`"
+        );
+        assert_eq!(
+            errors("Pattern p():\n return [1:4] Crescendo(fff)")[0].to_string(),
+            "\"fff\" is not a valid value for `Crescendo`"
+        );
+    }
+
+    #[test]
+    fn test_parse_rests() {
+        let input = r#"
+            Pattern gap():
+                return [1:4] Wait() + [5:8] Rest() + [9] Wait(4 bars) + [17] Rest(1 bar)
+        "#;
+
+        let tokens = lexer::tokenize(input);
+        let ast = Parser::new(tokens).parse().unwrap();
+        let TopLevel::Pattern(pattern) = &ast[0] else {
+            panic!("Expected pattern node");
+        };
+        let rests: Vec<_> = pattern
+            .events
+            .iter()
+            .map(|event| match event {
+                PatternEvent::Wait { bars, duration, .. } => (*bars, *duration),
+                _ => panic!("Expected rest"),
+            })
+            .collect();
+        assert_eq!(
+            rests,
+            vec![
+                (None, (1, 4)),
+                (None, (5, 8)),
+                (Some(4), (9, 9)),
+                (Some(1), (17, 17)),
+            ]
+        );
+
+        let errors = |input: &str| Parser::new(lexer::tokenize(input)).parse().unwrap_err();
+        assert!(matches!(
+            errors("Pattern p():\n return [1] Note(C)")[0],
+            ParseError::MissingLastStep { .. }
+        ));
+        assert!(matches!(
+            errors("Pattern p():\n return [1:16] Wait(4 bars)")[0],
+            ParseError::BarRestWithLastStep { .. }
+        ));
+        assert_eq!(
+            errors("Pattern p():\n return [1] Wait(4 beats)")[0].to_string(),
+            "\"beats\" is not a valid value for `Wait`"
+        );
+    }
+
+    #[test]
+    fn test_parse_pattern_parameters() {
+        let input = r#"
+            Pattern groove(root, vel):
+                return [1:8] Note(root, vel=vel) + [9:16] Pitch(C4)
+
+            Section Intro:
+                Channel bass:
+                    return groove(Am, 90) + groove(C#m7, 100) + groove()
+        "#;
+
+        let tokens = lexer::tokenize(input);
+        let ast = Parser::new(tokens).parse().unwrap();
+        let TopLevel::Pattern(pattern) = &ast[0] else {
+            panic!("Expected pattern node");
+        };
+        let params: Vec<_> = pattern
+            .params
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(params, vec!["root", "vel"]);
+        assert!(matches!(
+            &pattern.events[0],
+            PatternEvent::Note {
+                chord,
+                velocity: Some(Value::Parameter(vel)),
+                ..
+            } if chord == "root" && vel == "vel"
+        ));
+
+        let TopLevel::Section(section) = &ast[1] else {
+            panic!("Expected section node");
+        };
+        let calls: Vec<_> = section.channels[0]
+            .pattern_calls
+            .iter()
+            .flat_map(Sequence::calls)
+            .map(|call| {
+                let args: Vec<_> = call.args.iter().map(|(arg, _)| arg.clone()).collect();
+                (call.name.as_str(), args)
+            })
+            .collect();
+        assert_eq!(
+            calls,
+            vec![
+                (
+                    "groove",
+                    vec![Argument::Symbol("Am".to_string()), Argument::Number(90)]
+                ),
+                (
+                    "groove",
+                    vec![Argument::Symbol("C#m7".to_string()), Argument::Number(100)]
+                ),
+                ("groove", vec![]),
+            ]
+        );
+        let (_, arg) = &section.channels[0].pattern_calls[1].calls()[0].args[0];
+        assert_eq!(&input[arg.range()], "C#m7");
+    }
+
+    #[test]
+    fn test_parse_section_parameters() {
+        let input = r#"
+            Section Verse(key, vel):
+                tempo: 90
+                Channel bass:
+                    return groove(key, vel)
+
+            Song HotlineBling:
+                return Verse(Am, 90) + Verse(Dm, 60) + Outro()
+        "#;
+
+        let tokens = lexer::tokenize(input);
+        let ast = Parser::new(tokens).parse().unwrap();
+        let TopLevel::Section(section) = &ast[0] else {
+            panic!("Expected section node");
+        };
+        let params: Vec<_> = section
+            .params
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(params, vec!["key", "vel"]);
+        assert_eq!(
+            section.channels[0].pattern_calls[0].calls()[0].args[0].0,
+            Argument::Symbol("key".to_string())
+        );
+
+        let TopLevel::Song(song) = &ast[1] else {
+            panic!("Expected song node");
+        };
+        let calls: Vec<_> = song
+            .entry_sections
+            .iter()
+            .flat_map(Sequence::calls)
+            .map(|call| {
+                let args: Vec<_> = call.args.iter().map(|(arg, _)| arg.to_string()).collect();
+                (call.name.as_str(), args)
+            })
+            .collect();
+        assert_eq!(
+            calls,
+            vec![
+                ("Verse", vec!["Am".to_string(), "90".to_string()]),
+                ("Verse", vec!["Dm".to_string(), "60".to_string()]),
+                ("Outro", vec![]),
+            ]
+        );
+        assert_eq!(
+            &input[song.entry_sections[1].calls()[0].span.range()],
+            "Verse(Dm, 60)"
+        );
+    }
+
+    #[test]
+    fn test_parse_repeats() {
+        let input = r#"
+            Section Intro:
+                Channel lead:
+                    return intro() * 2 + repeat 3 { verse() + fill() * 2 } + outro()
+
+            Song HotlineBling:
+                return Intro() * 4
+        "#;
+
+        let tokens = lexer::tokenize(input);
+        let ast = Parser::new(tokens).parse().unwrap();
+        let TopLevel::Section(section) = &ast[0] else {
+            panic!("Expected section node");
+        };
+        let calls = &section.channels[0].pattern_calls;
+        assert_eq!(calls.len(), 3);
+        assert!(matches!(calls[0], Sequence::Repeat { count: 2, .. }));
+        let Sequence::Repeat { count, body, span } = &calls[1] else {
+            panic!("Expected repeat block");
+        };
+        assert_eq!(*count, 3);
+        assert_eq!(body.len(), 2);
+        assert_eq!(&input[span.range()], "repeat 3 { verse() + fill() * 2 }");
+        let played: Vec<_> = calls
+            .iter()
+            .flat_map(Sequence::played)
+            .map(|call| call.name.as_str())
+            .collect();
+        assert_eq!(
+            played,
+            vec![
+                "intro", "intro", "verse", "fill", "fill", "verse", "fill", "fill", "verse",
+                "fill", "fill", "outro",
+            ]
+        );
+
+        let TopLevel::Song(song) = &ast[1] else {
+            panic!("Expected song node");
+        };
+        assert_eq!(song.entry_sections[0].played().count(), 4);
+        assert_eq!(song.entry_sections[0].calls().len(), 1);
+    }
+
+    #[test]
+    fn test_parse_transforms() {
+        let input = r#"
+            Section Intro:
+                Channel lead:
+                    return intro() ^ +5 + intro().transpose(-12) ^ 2 * 2
+
+            Song HotlineBling:
+                return Intro().transpose(7)
+        "#;
+
+        let tokens = lexer::tokenize(input);
+        let ast = Parser::new(tokens).parse().unwrap();
+        let TopLevel::Section(section) = &ast[0] else {
+            panic!("Expected section node");
+        };
+        let calls: Vec<_> = section.channels[0]
+            .pattern_calls
+            .iter()
+            .flat_map(Sequence::calls)
+            .collect();
+        let transforms: Vec<Vec<Transform>> = calls
+            .iter()
+            .map(|call| call.transforms.iter().map(|(t, _)| t.clone()).collect())
+            .collect();
+        assert_eq!(
+            transforms,
+            vec![
+                vec![Transform::Transpose(5)],
+                vec![Transform::Transpose(-12), Transform::Transpose(2)],
+            ]
+        );
+        assert_eq!(&input[calls[0].transforms[0].1.range()], "^ +5");
+        assert_eq!(&input[calls[1].span.range()], "intro().transpose(-12) ^ 2");
+        assert!(matches!(
+            section.channels[0].pattern_calls[1],
+            Sequence::Repeat { count: 2, .. }
+        ));
+
+        let TopLevel::Song(song) = &ast[1] else {
+            panic!("Expected song node");
+        };
+        let call = song.entry_sections[0].calls()[0];
+        assert_eq!(call.transforms[0].0, Transform::Transpose(7));
+
+        let errors = Parser::new(lexer::tokenize("Song S:\n\treturn Intro().shift(2)"))
+            .parse()
+            .unwrap_err();
+        assert!(matches!(
+            &errors[0],
+            ParseError::UnknownTransform { name, .. } if name == "shift"
+        ));
+    }
+
+    #[test]
+    fn test_parse_motif_transforms() {
+        let input = r#"
+            Section Intro:
+                Channel lead:
+                    return motif().augment(2).transpose(-12) + motif().reverse().rotate(-3) + motif().diminish(2).invert(around=C#4)
+        "#;
+
+        let tokens = lexer::tokenize(input);
+        let ast = Parser::new(tokens).parse().unwrap();
+        let TopLevel::Section(section) = &ast[0] else {
+            panic!("Expected section node");
+        };
+        let transforms: Vec<Vec<Transform>> = section.channels[0]
+            .pattern_calls
+            .iter()
+            .flat_map(Sequence::calls)
+            .map(|call| call.transforms.iter().map(|(t, _)| t.clone()).collect())
+            .collect();
+        assert_eq!(
+            transforms,
+            vec![
+                vec![Transform::Augment(2), Transform::Transpose(-12)],
+                vec![Transform::Reverse, Transform::Rotate(-3)],
+                vec![
+                    Transform::Diminish(2),
+                    Transform::Invert {
+                        around: "C#4".to_string()
+                    },
+                ],
+            ]
+        );
+
+        let errors = Parser::new(lexer::tokenize("Song S:\n\treturn Intro().invert(axis=C4)"))
+            .parse()
+            .unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "unknown `invert` option \"axis\", expected `around`"
+        );
+    }
+
+    #[test]
+    fn test_parse_error_reports_expected_and_found() {
+        let input = "Pattern intro(: return [1:8] Note(C)";
+
+        let tokens = lexer::tokenize(input);
+        let mut parser = Parser::new(tokens);
+        let errors = parser.parse().unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![ParseError::UnexpectedToken {
+                expected: vec![Token::RParen],
+                found: Token::Colon,
+                text: ":".to_string(),
+                span: Span {
+                    start: 14,
+                    end: 15,
+                    line: 1,
+                    column: 15,
+                },
+            }]
+        );
+        assert_eq!(errors[0].to_string(), "expected `)`, found `:`");
+
+        let tokens = lexer::tokenize("Song S:\n return 5");
+        let errors = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "expected identifier, found number \"5\""
+        );
+    }
+
+    #[test]
+    fn test_parse_error_at_end_of_input() {
+        let input = "Song HotlineBling:";
+
+        let tokens = lexer::tokenize(input);
+        let mut parser = Parser::new(tokens);
+        let errors = parser.parse().unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![ParseError::UnexpectedEof {
+                expected: vec![Token::Return],
+                span: Span {
+                    start: 18,
+                    end: 18,
+                    line: 1,
+                    column: 19,
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_recovers_at_next_top_level_item() {
+        let input = r#"
+            Pattern intro():
+                return [1:8] Nota(Am)
+
+            Section Intro
+                Channel name_a:
+                    return intro()
+
+            Song HotlineBling:
+                return Intro()
+        "#;
+
+        let tokens = lexer::tokenize(input);
+        let mut parser = Parser::new(tokens);
+        let errors = parser.parse().unwrap_err();
+
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(&errors[0], ParseError::UnknownEvent { name, .. } if name == "Nota"));
+        assert!(matches!(
+            &errors[1],
+            ParseError::UnexpectedToken {
+                found: Token::Channel,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_ast_nodes_carry_spans() {
+        let input = "Pattern intro():\n    return [1:8] Note(Am) + [9:16] Wait()\n\nSection Intro:\n    Channel lead:\n        return intro() + intro()\n";
+
+        let tokens = lexer::tokenize(input);
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse().unwrap();
+
+        let TopLevel::Pattern(pattern) = &ast[0] else {
+            panic!("Expected pattern node");
+        };
+        assert_eq!((pattern.span.line, pattern.span.column), (1, 1));
+        let note = pattern.events[0].span();
+        assert_eq!(&input[note.range()], "[1:8] Note(Am)");
+        assert_eq!((note.line, note.column), (2, 12));
+        assert!(matches!(pattern.events[1], PatternEvent::Wait { .. }));
+
+        let TopLevel::Section(section) = &ast[1] else {
+            panic!("Expected section node");
+        };
+        assert_eq!(section.span.line, 4);
+        let channel = &section.channels[0];
+        assert_eq!((channel.span.line, channel.span.column), (5, 5));
+        let call = channel.pattern_calls[1].calls()[0];
+        assert_eq!(call.name, "intro");
+        assert_eq!(&input[call.span.range()], "intro()");
+        assert_eq!((call.span.line, call.span.column), (6, 26));
+    }
+
+    #[test]
+    fn test_parse_skips_comments() {
+        let input = r#"
+            // The intro riff
+            Pattern intro(): // two bars
+                return [1:8] Note(Am) /* then */ + [9:16] Note(C)
+
+            /* Played once
+               at the start */
+            Section Intro:
+                Channel lead:
+                    return intro()
+        "#;
+
+        let tokens = lexer::tokenize(input);
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse().unwrap();
+
+        assert_eq!(ast.len(), 2);
+        let TopLevel::Pattern(pattern) = &ast[0] else {
+            panic!("Expected pattern node");
+        };
+        assert_eq!(pattern.events.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_channel_instrument() {
+        let input = r#"
+            Instrument piano:
+                type: 4
+                midi_path: piano
+
+            Section Intro:
+                Channel lead uses piano:
+                    return intro()
+                Channel pad:
+                    return intro()
+        "#;
+
+        let tokens = lexer::tokenize(input);
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse().unwrap();
+
+        let TopLevel::Instrument(instrument) = &ast[0] else {
+            panic!("Expected instrument node");
+        };
+        assert_eq!(instrument.type_, "4");
+        let TopLevel::Section(section) = &ast[1] else {
+            panic!("Expected section node");
+        };
+        let (instrument, span) = section.channels[0].instrument.as_ref().unwrap();
+        assert_eq!(instrument, "piano");
+        assert_eq!((span.line, span.column), (7, 35));
+        assert!(section.channels[1].instrument.is_none());
+    }
+
+    #[test]
+    fn test_parse_tempo_and_time_signature() {
+        let input = r#"
+            Pattern speedup():
+                return [1:8] Note(Am) + [9:9] Tempo(140)
+
+            Section Chorus:
+                tempo: 100
+                time_signature: 6/8
+                Channel lead:
+                    return speedup()
+
+            Song HotlineBling:
+                time_signature: 3/4
+                tempo: 92
+                return Chorus()
+        "#;
+
+        let tokens = lexer::tokenize(input);
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse().unwrap();
+
+        let TopLevel::Pattern(pattern) = &ast[0] else {
+            panic!("Expected pattern node");
+        };
+        assert!(matches!(
+            pattern.events[1],
+            PatternEvent::Tempo {
+                bpm: 140,
+                duration: (9, 9),
+                ..
+            }
+        ));
+        let TopLevel::Section(section) = &ast[1] else {
+            panic!("Expected section node");
+        };
+        assert_eq!(section.tempo.map(|(bpm, _)| bpm), Some(100));
+        let (meter, span) = section.time_signature.unwrap();
+        assert_eq!((meter.numerator, meter.denominator), (6, 8));
+        assert_eq!(&input[span.range()], "time_signature: 6/8");
+        let TopLevel::Song(song) = &ast[2] else {
+            panic!("Expected song node");
+        };
+        let (bpm, span) = song.tempo.unwrap();
+        assert_eq!(bpm, 92);
+        assert_eq!(&input[span.range()], "tempo: 92");
+        assert_eq!(song.time_signature.unwrap().0.to_string(), "3/4");
+    }
 }
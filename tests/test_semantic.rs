@@ -0,0 +1,847 @@
+#[cfg(test)]
+mod tests {
+    use cricket::{
+        lexer,
+        midigen::MAX_CHANNELS,
+        parser::Parser,
+        semantic::{DefinitionKind, Semantic, SemanticError, SemanticWarning},
+    };
+
+    fn analyze(input: &str) -> Result<(), Vec<SemanticError>> {
+        let tokens = lexer::tokenize(input);
+        let ast = Parser::new(tokens).parse().unwrap();
+        Semantic::new(ast).analyze()
+    }
+
+    #[test]
+    fn test_valid_song() {
+        let input = r#"
+            Pattern intro():
+                return [1:8] Note(Am)
+
+            Section Intro:
+                Channel name_a:
+                    return intro()
+
+            Song HotlineBling:
+                return Intro() + Intro()
+        "#;
+
+        assert_eq!(analyze(input), Ok(()));
+    }
+
+    #[test]
+    fn test_reports_all_errors_in_source_order() {
+        let input = r#"
+            Pattern intro():
+                return [1:8] Note(Am)
+
+            Pattern intro():
+                return [1:8] Note(C)
+
+            Section Intro:
+                Channel name_a:
+                    return intro()
+                Channel name_a:
+                    return intro()
+
+            Song HotlineBling:
+                return Intro() + Outro()
+        "#;
+
+        let errors = analyze(input).unwrap_err();
+
+        assert_eq!(errors.len(), 3);
+        match &errors[0] {
+            SemanticError::DuplicateDefinition {
+                kind,
+                name,
+                span,
+                first,
+            } => {
+                assert_eq!(*kind, DefinitionKind::Pattern);
+                assert_eq!(name, "intro");
+                assert_eq!((first.line, span.line), (2, 5));
+            }
+            other => panic!("Expected duplicate definition, got {:?}", other),
+        }
+        assert!(matches!(
+            &errors[1],
+            SemanticError::DuplicateChannel { channel, span, .. }
+                if channel == "name_a" && span.line == 11
+        ));
+        match &errors[2] {
+            SemanticError::UndefinedSection { section, span, .. } => {
+                assert_eq!(section, "Outro");
+                assert_eq!((span.line, span.column), (15, 34));
+            }
+            other => panic!("Expected undefined section, got {:?}", other),
+        }
+        assert_eq!(
+            errors[2].to_string(),
+            "song \"HotlineBling\" uses section \"Outro\", which is not defined"
+        );
+    }
+
+    #[test]
+    fn test_undefined_patterns_and_unknown_chords() {
+        let input = r#"
+            Pattern intro():
+                return [1:8] Note(Am) + [9:16] Note(Ax7) + [9:16] Note(C/H)
+
+            Section Intro:
+                Channel name_a:
+                    return intro() + intro9()
+                Channel name_b:
+                    return outro()
+
+            Song HotlineBling:
+                return Intro()
+        "#;
+
+        let errors = analyze(input).unwrap_err();
+        let messages: Vec<_> = errors
+            .iter()
+            .map(|e| (e.span().line, e.span().column, e.to_string()))
+            .collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    3,
+                    41,
+                    "pattern \"intro\" plays \"Ax7\", which is not a valid chord: \"x7\" is not a known chord quality".to_string()
+                ),
+                (
+                    3,
+                    60,
+                    "pattern \"intro\" plays \"C/H\", which is not a valid chord: bass note \"H\" is not a note name from A to G".to_string()
+                ),
+                (
+                    7,
+                    38,
+                    "channel \"name_a\" in section \"Intro\" calls pattern \"intro9\", which is not defined".to_string()
+                ),
+                (
+                    9,
+                    28,
+                    "channel \"name_b\" in section \"Intro\" calls pattern \"outro\", which is not defined".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_warns_about_unreachable_definitions() {
+        let input = r#"
+            Instrument piano:
+                type: Piano
+                midi_path: piano
+
+            Pattern intro():
+                return [1:16] Note(Am)
+
+            Pattern verse():
+                return [1:16] Note(C)
+
+            Pattern bridge():
+                return [1:16] Note(F)
+
+            Section Intro:
+                Channel name_a:
+                    return intro()
+
+            Section Bridge:
+                Channel name_a:
+                    return bridge()
+
+            Song HotlineBling:
+                return Intro()
+        "#;
+
+        let tokens = lexer::tokenize(input);
+        let ast = Parser::new(tokens).parse().unwrap();
+        let mut semantic = Semantic::new(ast);
+        assert_eq!(semantic.analyze(), Ok(()));
+
+        let warnings: Vec<_> = semantic
+            .warnings()
+            .iter()
+            .map(|w| match w {
+                SemanticWarning::Unused { kind, name, span } => (*kind, name.as_str(), span.line),
+                other => panic!("Expected unused warning, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                (DefinitionKind::Instrument, "piano", 2),
+                (DefinitionKind::Pattern, "verse", 9),
+                (DefinitionKind::Pattern, "bridge", 12),
+                (DefinitionKind::Section, "Bridge", 19),
+            ]
+        );
+        assert_eq!(
+            semantic.warnings()[1].to_string(),
+            "pattern \"verse\" is never used"
+        );
+    }
+
+    #[test]
+    fn test_channel_instruments() {
+        let input = r#"
+            Instrument piano:
+                type: AcousticGrandPiano
+                midi_path: piano
+
+            Instrument theremin:
+                type: Theremin
+                midi_path: theremin
+
+            Pattern intro():
+                return [1:16] Note(Am)
+
+            Section Intro:
+                Channel lead uses piano:
+                    return intro()
+                Channel pad uses organ:
+                    return intro()
+
+            Song HotlineBling:
+                return Intro()
+        "#;
+
+        let tokens = lexer::tokenize(input);
+        let ast = Parser::new(tokens).parse().unwrap();
+        let mut semantic = Semantic::new(ast);
+        let errors = semantic.analyze().unwrap_err();
+
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(
+            &errors[0],
+            SemanticError::UnknownInstrumentType { instrument, type_, .. }
+                if instrument == "theremin" && type_ == "Theremin"
+        ));
+        assert!(matches!(
+            &errors[1],
+            SemanticError::UndefinedInstrument { channel, instrument, span, .. }
+                if channel == "pad" && instrument == "organ" && span.line == 16
+        ));
+        // `piano` is used by the lead channel, `theremin` by nobody.
+        assert_eq!(semantic.warnings().len(), 1);
+        assert!(semantic.warnings()[0].to_string().contains("\"theremin\""));
+    }
+
+    #[test]
+    fn test_tempo_range() {
+        let input = r#"
+            Pattern intro():
+                return [1:8] Note(Am) + [9:9] Tempo(2000)
+
+            Section Intro:
+                tempo: 0
+                Channel name_a:
+                    return intro()
+
+            Song HotlineBling:
+                tempo: 92
+                return Intro()
+        "#;
+
+        let errors = analyze(input).unwrap_err();
+        let messages: Vec<_> = errors
+            .iter()
+            .map(|e| (e.span().line, e.to_string()))
+            .collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    3,
+                    "tempo 2000 is out of range, expected 10 to 1000 BPM".to_string()
+                ),
+                (
+                    6,
+                    "tempo 0 is out of range, expected 10 to 1000 BPM".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_voicings() {
+        let input = r#"
+            Pattern comp():
+                return [1:4] Note(Am/1, inversion=2) + [5:8] Note(C, inversion=3) + [9:12] Note(C13, octave=9) + [13:16] Note(C/3)
+
+            Section Intro:
+                Channel name_a:
+                    return comp()
+
+            Song HotlineBling:
+                return Intro()
+        "#;
+
+        let errors = analyze(input).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "pattern \"comp\" cannot voice \"Am/1\": the inversion is set both in the chord symbol and as an option",
+                "pattern \"comp\" cannot voice \"C\": inversion 3 needs at least 4 chord notes, but there are 3",
+                "pattern \"comp\" cannot voice \"C13\": the chord reaches MIDI note 130, outside of the MIDI range 0 to 127",
+                "pattern \"comp\" plays \"C/3\", which is not a valid chord: inversion 3 needs at least 4 chord notes, but there are 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_voice_ranges() {
+        let input = r#"
+            Pattern verse():
+                voice_leading: smooth(C5, C3)
+                return [1:16] Note(C)
+
+            Section Intro:
+                Channel pad:
+                    voice_leading: smooth(C3, A3)
+                    return verse()
+                Channel keys:
+                    voice_leading: smooth(C3, H5)
+                    return verse()
+                Channel bass:
+                    voice_leading: smooth(C2, C3)
+                    return verse()
+
+            Song HotlineBling:
+                return Intro()
+        "#;
+
+        let errors = analyze(input).unwrap_err();
+        let ranges: Vec<_> = errors
+            .iter()
+            .map(|e| match e {
+                SemanticError::InvalidVoiceRange { low, high, span } => {
+                    (span.line, low.as_str(), high.as_str())
+                }
+                _ => panic!("unexpected error {e}"),
+            })
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![(3, "C5", "C3"), (8, "C3", "A3"), (11, "C3", "H5")]
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            "voice range C5 to C3 is invalid, it needs two pitches at least an octave apart, the lowest first"
+        );
+    }
+
+    #[test]
+    fn test_chords_outside_voice_ranges() {
+        let input = r#"
+            Pattern verse(chord):
+                return [1:8] Note(chord) + [9:16] Note(C13, octave=2)
+
+            Pattern wide():
+                voice_leading: smooth(C4, C5)
+                return [1:8] Note(C13) + [9:16] Note(G13)
+
+            Section Intro:
+                Channel pad:
+                    voice_leading: smooth(C4, C5)
+                    return verse(Am) + verse(Cmaj7) ^ +2 + verse(G13) + wide()
+                Channel keys:
+                    return verse(G13)
+
+            Song HotlineBling:
+                return Intro()
+        "#;
+
+        let errors = analyze(input).unwrap_err();
+        let messages: Vec<_> = errors
+            .iter()
+            .map(|e| (e.span().line, e.span().column, e.to_string()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    12,
+                    60,
+                    "pattern \"verse\" plays \"G13\", which cannot be voiced within the voice leading range C4 to C5".to_string()
+                ),
+                (
+                    12,
+                    73,
+                    "pattern \"wide\" plays \"G13\", which cannot be voiced within the voice leading range C4 to C5".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_pitches() {
+        let input = r#"
+            Pattern bass():
+                return [1:4] Pitch(E2) + [5:8] Pitch(G#9) + [9:12] Pitch(C) + [13:16] Pitch(H2)
+
+            Section Intro:
+                Channel name_a:
+                    return bass()
+
+            Song HotlineBling:
+                return Intro()
+        "#;
+
+        let errors = analyze(input).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "pattern \"bass\" plays \"G#9\", which is not a valid pitch: \"G#9\" is MIDI note 128, outside of the MIDI range 0 to 127",
+                "pattern \"bass\" plays \"C\", which is not a valid pitch: \"C\" needs an octave number after the note name, e.g. `C4`",
+                "pattern \"bass\" plays \"H2\", which is not a valid pitch: \"H2\" does not start with a note name from A to G",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_velocities_and_hairpins() {
+        let input = r#"
+            Pattern lead():
+                return [1:4] Note(Am, vel=0) + [5:8] Pitch(C4, vel=128) + [9:12] Note(C, vel=127) + [1:8] Crescendo(f, p) + [9:16] Diminuendo(p, pp)
+
+            Section Intro:
+                Channel name_a:
+                    return lead()
+
+            Song HotlineBling:
+                return Intro()
+        "#;
+
+        let errors = analyze(input).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "velocity 0 is out of range, expected 1 to 127",
+                "velocity 128 is out of range, expected 1 to 127",
+                "crescendo from f to p does not go the right way",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bar_rests() {
+        let input = r#"
+            Pattern gap():
+                return [1:4] Note(Am) + [5] Wait(1 bar)
+
+            Pattern nothing():
+                return [1] Rest(0 bars)
+
+            Section Intro:
+                time_signature: 3/4
+                Channel name_a:
+                    return gap() + nothing()
+
+            Song HotlineBling:
+                return Intro()
+        "#;
+
+        let tokens = lexer::tokenize(input);
+        let ast = Parser::new(tokens).parse().unwrap();
+        let mut semantic = Semantic::new(ast);
+        let errors = semantic.analyze().unwrap_err();
+        assert_eq!(
+            errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+            vec!["a rest of whole bars has to last at least one bar"]
+        );
+        // One beat and a bar of 3/4 make four beats, which is not a whole
+        // number of bars.
+        assert_eq!(
+            semantic
+                .warnings()
+                .iter()
+                .map(|w| w.to_string())
+                .collect::<Vec<_>>(),
+            vec!["pattern \"gap\" is 16 steps long, which is not a whole number of 3/4 bars"]
+        );
+    }
+
+    #[test]
+    fn test_pattern_arguments() {
+        let input = r#"
+            Pattern groove(root, vel):
+                return [1:8] Note(root, vel=vel) + [9:16] Pitch(C4, vel=loud)
+
+            Pattern bass(note, note):
+                return [1:16] Pitch(note)
+
+            Section Intro:
+                Channel name_a:
+                    return groove(Am, 90) + groove(Am) + groove(90, Am) + groove(Hm, 200)
+                Channel name_b:
+                    return bass(E2, G#9)
+
+            Song HotlineBling:
+                return Intro()
+        "#;
+
+        let errors = analyze(input).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "pattern \"groove\" uses parameter \"loud\", which it does not declare",
+                "pattern \"bass\" declares parameter \"note\" more than once",
+                "pattern \"groove\" takes 2 argument(s), but is called with 1",
+                "argument \"90\" for parameter \"root\" of pattern \"groove\" is not a chord",
+                "argument \"Am\" for parameter \"vel\" of pattern \"groove\" is not a velocity from 1 to 127",
+                "argument \"Hm\" for parameter \"root\" of pattern \"groove\" is not a chord",
+                "argument \"200\" for parameter \"vel\" of pattern \"groove\" is not a velocity from 1 to 127",
+                "argument \"G#9\" for parameter \"note\" of pattern \"bass\" is not a pitch",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chord_arguments_are_voiced() {
+        let input = r#"
+            Pattern groove(root):
+                return [1:16] Note(root, octave=9)
+
+            Section Verse(key):
+                Channel name_a:
+                    return groove(C13) + groove(key)
+
+            Song HotlineBling:
+                return Verse(C) + Verse(Am)
+        "#;
+
+        let errors = analyze(input).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "pattern \"groove\" cannot voice \"C13\": the chord reaches MIDI note 130, outside of the MIDI range 0 to 127",
+                "pattern \"groove\" cannot voice \"Am\": the chord reaches MIDI note 129, outside of the MIDI range 0 to 127",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_section_arguments() {
+        let input = r#"
+            Pattern groove(root, vel):
+                return [1:16] Note(root, vel=vel)
+
+            Section Verse(key, key):
+                Channel name_a:
+                    return groove(Am, 90)
+
+            Section Chorus(key, vel):
+                Channel name_a:
+                    return groove(key, vel) + groove(C, vel)
+
+            Song HotlineBling:
+                return Verse(Am, Am) + Chorus(Dm) + Chorus(Dm, 80) + Chorus(90, Dm)
+        "#;
+
+        let errors = analyze(input).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "section \"Verse\" declares parameter \"key\" more than once",
+                "section \"Chorus\" takes 2 argument(s), but is called with 1",
+                "argument \"90\" for parameter \"root\" of pattern \"groove\" is not a chord",
+                "argument \"Dm\" for parameter \"vel\" of pattern \"groove\" is not a velocity from 1 to 127",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repeats_play_at_least_once() {
+        let input = r#"
+            Pattern intro():
+                return [1:16] Note(Am)
+
+            Section Intro:
+                Channel name_a:
+                    return intro() * 2 + repeat 0 { intro() }
+
+            Song HotlineBling:
+                return Intro() * 0 + Missing() * 2
+        "#;
+
+        let errors = analyze(input).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "a repeat has to play at least once",
+                "a repeat has to play at least once",
+                "song \"HotlineBling\" uses section \"Missing\", which is not defined",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transpositions_stay_in_range() {
+        let input = r#"
+            Pattern riff(root):
+                return [1:4] Note(root) + [5:8] Pitch(C8)
+
+            Section Verse(key):
+                Channel lead:
+                    return riff(Am) ^ +12 + riff(Am) ^ +24 + riff(key).transpose(-60)
+
+            Song HotlineBling:
+                return Verse(C) + Verse(Am).transpose(-10)
+        "#;
+
+        let errors = analyze(input).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "transposing pattern \"riff\" by 24 semitones takes \"C8\" outside of the MIDI range 0 to 127",
+                "transposing pattern \"riff\" by -70 semitones takes \"Am\" outside of the MIDI range 0 to 127",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_huge_transpositions() {
+        let input = r#"
+            Pattern groove():
+                return [1:16] Note(Am)
+
+            Section Verse:
+                Channel name_a:
+                    return groove() ^ +2147483647 ^ +2147483647
+
+            Section Chorus:
+                Channel name_a:
+                    return groove() ^ +2147483647
+
+            Song HotlineBling:
+                return Verse() + Chorus() ^ +2147483647
+        "#;
+
+        let errors = analyze(input).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        let out_of_range = "transposing pattern \"groove\" by 2147483647 semitones takes \"Am\" outside of the MIDI range 0 to 127";
+        assert_eq!(messages, vec![out_of_range; 3]);
+    }
+
+    #[test]
+    fn test_songs_too_long_for_a_midi_file() {
+        let input = r#"
+            Pattern groove():
+                return [1:4] Note(Am)
+
+            Section Verse:
+                Channel name_a:
+                    return groove() * 3000000
+
+            Section Chorus:
+                Channel name_a:
+                    return repeat 4294967295 { groove().augment(60) * 4294967295 }
+
+            Song HotlineBling:
+                return Verse()
+
+            Song Endless:
+                return Chorus()
+        "#;
+
+        let errors = analyze(input).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "song \"HotlineBling\" lasts longer than a MIDI file can hold",
+                "song \"Endless\" lasts longer than a MIDI file can hold",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_transforms() {
+        let input = r#"
+            Pattern motif():
+                return [1:4] Pitch(E4) + [6:8] Pitch(D4)
+
+            Pattern chords():
+                return [1:4] Note(Am)
+
+            Section Verse:
+                Channel lead:
+                    return motif().augment(2) + motif().diminish(2) + chords().invert(around=C4) + motif().invert(around=H4)
+
+            Song HotlineBling:
+                return Verse().reverse() + Verse() ^ -2
+        "#;
+
+        let errors = analyze(input).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "pattern \"motif\" cannot be transformed: diminishing by 2 would leave steps [6:8] between two steps",
+                "pattern \"chords\" cannot be transformed: `invert` only mirrors single pitches, but the pattern plays chord \"Am\"",
+                "pattern \"motif\" cannot be transformed: \"H4\" is not a valid pitch to invert around: \"H4\" does not start with a note name from A to G",
+                "section \"Verse\" can only be transposed, `reverse` applies to pattern calls",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_steps() {
+        let input = r#"
+            Pattern intro():
+                return [0:8] Note(Am) + [9:16] Note(C) + [12:10] Wait()
+
+            Section Intro:
+                Channel name_a:
+                    return intro()
+
+            Song HotlineBling:
+                return Intro()
+        "#;
+
+        let errors = analyze(input).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(
+            errors[0],
+            SemanticError::InvalidSteps {
+                first: 0,
+                last: 8,
+                ..
+            }
+        ));
+        assert!(matches!(
+            errors[1],
+            SemanticError::InvalidSteps {
+                first: 12,
+                last: 10,
+                ..
+            }
+        ));
+        assert_eq!(
+            errors[1].to_string(),
+            "steps [12:10] are invalid, steps are counted from 1 and the last step cannot come before the first"
+        );
+    }
+
+    #[test]
+    fn test_too_many_channels() {
+        let channels = |count: usize| -> String {
+            (0..count)
+                .map(|i| format!("Channel part{i}:\n return bar()\n"))
+                .collect()
+        };
+        let source = |count: usize| {
+            format!(
+                "Pattern bar():\n return [1:16] Note(C)\n\
+                 Section Tutti:\n {}\n\
+                 Section Coda:\n {}\n\
+                 Song Symphony:\n return Tutti() + Coda()\n",
+                channels(count),
+                channels(count),
+            )
+        };
+
+        // Channels with the same name in different sections share a slot.
+        assert_eq!(analyze(&source(MAX_CHANNELS)), Ok(()));
+
+        let errors = analyze(&source(MAX_CHANNELS + 1)).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "song \"Symphony\" uses 1921 differently named channels, but at most 1920 fit on the available MIDI ports"
+        );
+    }
+
+    #[test]
+    fn test_time_signatures_and_partial_bars() {
+        let input = r#"
+            Pattern waltz():
+                return [1:4] Note(C) + [5:12] Note(G)
+
+            Pattern half():
+                return [1:8] Note(Am)
+
+            Section Verse:
+                time_signature: 3/4
+                Channel lead:
+                    return waltz() + half()
+
+            Section Chorus:
+                Channel lead:
+                    return waltz() + half() + half()
+
+            Section Bridge:
+                time_signature: 5/6
+                Channel lead:
+                    return half()
+
+            Song HotlineBling:
+                time_signature: 4/4
+                return Verse() + Chorus() + Bridge()
+        "#;
+
+        let tokens = lexer::tokenize(input);
+        let ast = Parser::new(tokens).parse().unwrap();
+        let mut semantic = Semantic::new(ast);
+        let errors = semantic.analyze().unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "time signature 5/6 is invalid, the numerator must be at least 1 and the denominator a power of two"
+        );
+        assert_eq!(errors[0].span().line, 18);
+
+        // `waltz` fills a 3/4 bar but not a 4/4 one; `half` fills neither.
+        let warnings: Vec<_> = semantic.warnings().iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "pattern \"waltz\" is 12 steps long, which is not a whole number of 4/4 bars",
+                "pattern \"half\" is 8 steps long, which is not a whole number of 3/4 bars",
+                "pattern \"half\" is 8 steps long, which is not a whole number of 4/4 bars",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bar_rests_in_invalid_time_signatures() {
+        let input = r#"
+            Pattern pause():
+                return [1:4] Note(Am) + [5] Wait(1 bars)
+
+            Section Verse:
+                time_signature: 3/0
+                Channel lead:
+                    return pause()
+
+            Song HotlineBling:
+                return Verse()
+        "#;
+
+        let errors = analyze(input).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "time signature 3/0 is invalid, the numerator must be at least 1 and the denominator a power of two"
+            ]
+        );
+    }
+}